@@ -1,43 +1,63 @@
 use ndarray::{Array, Ix3};
-use crate::utils::generate_edge;
+use crate::bitmask::BitMask;
 pub struct APL {
-    ref_arr: Array<bool, Ix3>,
-    other_arr: Array<bool, Ix3>,
+    ref_edge: BitMask,
+    other_edge: BitMask,
     diff_count: u32,
-    other_total_count: u32
+    other_total_count: u32,
+    roi: Option<BitMask>,
 }
 impl APL {
     pub fn new(ref_arr: &Array<bool, Ix3>, other_arr: &Array<bool, Ix3>) -> APL {
+        APL::from_masks(&BitMask::from_array(ref_arr), &BitMask::from_array(other_arr))
+    }
+    /// APL of two bit-packed masks; in-plane edges are extracted word-parallel.
+    pub fn from_masks(ref_mask: &BitMask, other_mask: &BitMask) -> APL {
         APL {
-            ref_arr: generate_edge(ref_arr, true),
-            other_arr: generate_edge(other_arr, true),
+            ref_edge: ref_mask.edge(true),
+            other_edge: other_mask.edge(true),
             diff_count: 0,
             other_total_count: 0,
+            roi: None,
         }
     }
-    pub fn execute(&mut self) {
-        let mut ref_val: &bool;
-        let mut other_val: &bool;
-        for z in 0..self.ref_arr.shape()[0] {
-            for y in 0..self.ref_arr.shape()[1] {
-                for x in 0..self.ref_arr.shape()[2] {
-                    ref_val = self.ref_arr.get([z, y, x]).unwrap();
-                    other_val = self.other_arr.get([z, y, x]).unwrap();
-                    if *other_val {
-                        self.other_total_count += 1;
-                        if !*ref_val {
-                            self.diff_count += 1;
-                        }
-                    }
-                }
-            }
+    /// APL from precomputed counts of other edge voxels missing from the reference edge
+    /// and of all other edge voxels.
+    pub fn from_counts(diff_count: u32, other_total_count: u32) -> APL {
+        APL {
+            ref_edge: BitMask::new([0, 0, 0]),
+            other_edge: BitMask::new([0, 0, 0]),
+            diff_count,
+            other_total_count,
+            roi: None,
         }
     }
+    /// Only count edge voxels inside `roi`; everything outside is ignored.
+    pub fn with_roi(self, roi: &Array<bool, Ix3>) -> APL {
+        self.with_roi_mask(BitMask::from_array(roi))
+    }
+    /// Like `with_roi`; an ROI that is already set is intersected with `roi`.
+    pub fn with_roi_mask(mut self, roi: BitMask) -> APL {
+        self.roi = Some(match self.roi {
+            Some(current) => current.and(&roi),
+            None => roi,
+        });
+        self
+    }
+    /// Ignore edge voxels inside `ignore`.
+    pub fn with_ignore(self, ignore: &Array<bool, Ix3>) -> APL {
+        self.with_roi(&ignore.mapv(|v| !v))
+    }
+    pub fn execute(&mut self) {
+        let roi = self.roi.as_ref();
+        self.other_total_count = self.other_edge.count_combined(&self.ref_edge, roi, |o, _| o) as u32;
+        self.diff_count = self.other_edge.count_combined(&self.ref_edge, roi, |o, r| o & !r) as u32;
+    }
 
     pub fn apl(&self) -> u32 {
         self.diff_count
     }
-    fn apl_normalized(&self) -> f32 {
+    pub fn apl_normalized(&self) -> f32 {
         (self.diff_count as f32) / (self.other_total_count as f32)
     }
 }
@@ -69,8 +89,8 @@ mod test_apl {
                 [0, 1, 1, 1, 0],
                 [0, 1, 1, 1, 0]]]);
 
-        let src = src.mapv(|x| (x != 0));
-        let dst = dst.mapv(|x| (x != 0));
+        let src = src.mapv(|x| x != 0);
+        let dst = dst.mapv(|x| x != 0);
 
         let mut f = APL::new(&src, &dst);
         f.execute();
@@ -79,4 +99,18 @@ mod test_apl {
         assert!(&f.apl().eq(&7));
         assert!(&f.apl_normalized().eq(&0.3888889));
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_apl_roi() {
+        let src = Array3::<bool>::from_elem([2, 4, 5], false);
+        let mut dst = Array3::<bool>::from_elem([2, 4, 5], false);
+        let mut roi = Array3::<bool>::from_elem([2, 4, 5], true);
+        for y in 0..3 {
+            dst[[1, y, 3]] = true;
+            roi[[1, y, 3]] = y == 0;
+        }
+
+        let mut f = APL::new(&src, &dst).with_roi(&roi);
+        f.execute();
+        assert!(&f.apl().eq(&1));
+    }
+}
@@ -0,0 +1,180 @@
+use ndarray::{Array, ArrayView3, Ix3};
+
+/// Boolean volume packed into 64-bit words, one bit per voxel. Every (z, y) row along x
+/// starts on a fresh word and unused bits at the end of a row are always zero, so whole
+/// words can be combined and counted with bitwise operations and `count_ones`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitMask {
+    shape: [usize; 3],
+    row_words: usize,
+    words: Vec<u64>,
+}
+
+impl BitMask {
+    /// All-false mask of the given shape.
+    pub fn new(shape: [usize; 3]) -> BitMask {
+        let row_words = shape[2].div_ceil(64);
+        BitMask {
+            shape,
+            row_words,
+            words: vec![0; shape[0] * shape[1] * row_words],
+        }
+    }
+    pub fn from_view(arr: ArrayView3<bool>) -> BitMask {
+        let shape = arr.shape();
+        let mut mask = BitMask::new([shape[0], shape[1], shape[2]]);
+        let row_words = mask.row_words;
+        for (r, row) in arr.rows().into_iter().enumerate() {
+            let words = &mut mask.words[r * row_words..(r + 1) * row_words];
+            for (x, val) in row.iter().enumerate() {
+                if *val {
+                    words[x / 64] |= 1 << (x % 64);
+                }
+            }
+        }
+        mask
+    }
+    pub fn from_array(arr: &Array<bool, Ix3>) -> BitMask {
+        BitMask::from_view(arr.view())
+    }
+    pub fn to_array(&self) -> Array<bool, Ix3> {
+        Array::from_shape_fn(self.shape, |(z, y, x)| self.get([z, y, x]))
+    }
+    pub fn shape(&self) -> [usize; 3] {
+        self.shape
+    }
+    pub fn len(&self) -> usize {
+        self.shape.iter().product()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn get(&self, idx: [usize; 3]) -> bool {
+        let word = (idx[0] * self.shape[1] + idx[1]) * self.row_words + idx[2] / 64;
+        (self.words[word] >> (idx[2] % 64)) & 1 == 1
+    }
+    /// Voxels set in both masks.
+    pub fn and(&self, other: &BitMask) -> BitMask {
+        assert_eq!(self.shape, other.shape);
+        let mut combined = self.clone();
+        for (w, o) in combined.words.iter_mut().zip(other.words.iter()) {
+            *w &= o;
+        }
+        combined
+    }
+    /// Number of true voxels.
+    pub fn count(&self) -> u64 {
+        self.words.iter().map(|w| w.count_ones() as u64).sum()
+    }
+    /// Bits of the last word of a row that hold voxels.
+    fn tail_bits(&self) -> u64 {
+        match self.shape[2] % 64 {
+            0 => u64::MAX,
+            n => (1 << n) - 1,
+        }
+    }
+    /// Number of voxels for which `op` sets the bit, restricted to `roi` if given. `op`
+    /// may complement its inputs; bits beyond the end of each row are never counted.
+    pub fn count_combined(&self, other: &BitMask, roi: Option<&BitMask>, op: impl Fn(u64, u64) -> u64) -> u64 {
+        assert_eq!(self.shape, other.shape);
+        if let Some(roi) = roi {
+            assert_eq!(self.shape, roi.shape, "roi must have the shape of the masks");
+        }
+        let tail = self.tail_bits();
+        let mut count = 0_u64;
+        for (i, (a, b)) in self.words.iter().zip(other.words.iter()).enumerate() {
+            let mut word = op(*a, *b);
+            if let Some(roi) = roi {
+                word &= roi.words[i];
+            }
+            if i % self.row_words == self.row_words - 1 {
+                word &= tail;
+            }
+            count += word.count_ones() as u64;
+        }
+        count
+    }
+    /// Voxels for which every neighbour in the 3x3x3 box (3x3 in-plane if `use_2d`) is set,
+    /// with voxels outside the volume counting as unset. Separable: x, then y, then z.
+    fn erode_box(&self, use_2d: bool) -> BitMask {
+        let rw = self.row_words;
+        let mut eroded = self.clone();
+        for row in eroded.words.chunks_mut(rw) {
+            let input = row.to_vec();
+            for (w, out) in row.iter_mut().enumerate() {
+                let prev = if w > 0 { input[w - 1] } else { 0 };
+                let next = if w + 1 < rw { input[w + 1] } else { 0 };
+                let left = (input[w] << 1) | (prev >> 63);
+                let right = (input[w] >> 1) | (next << 63);
+                *out = input[w] & left & right;
+            }
+        }
+        // Rows are `rw` words apart along y and `ny * rw` along z
+        let mut strides = vec![(rw, self.shape[1])];
+        if !use_2d {
+            strides.push((self.shape[1] * rw, self.shape[0]));
+        }
+        for (stride, extent) in strides {
+            let input = eroded.words.clone();
+            for (i, out) in eroded.words.iter_mut().enumerate() {
+                let pos = (i / stride) % extent;
+                let before = if pos > 0 { input[i - stride] } else { 0 };
+                let after = if pos + 1 < extent { input[i + stride] } else { 0 };
+                *out &= before & after;
+            }
+        }
+        eroded
+    }
+    /// Surface voxels: set voxels with at least one unset neighbour in the 3x3x3 box (3x3
+    /// in-plane if `use_2d`). Voxels on the volume border are always surface voxels.
+    pub fn edge(&self, use_2d: bool) -> BitMask {
+        let eroded = self.erode_box(use_2d);
+        let mut edge = self.clone();
+        for (w, e) in edge.words.iter_mut().zip(eroded.words.iter()) {
+            *w &= !e;
+        }
+        edge
+    }
+}
+
+#[cfg(test)]
+mod test_bitmask {
+    use ndarray::{s, Array3};
+    use crate::bitmask::BitMask;
+
+    #[test]
+    fn test_bitmask_roundtrip_and_counts() {
+        let mut a = Array3::<bool>::from_elem([3, 4, 70], false);
+        let mut b = Array3::<bool>::from_elem([3, 4, 70], false);
+        a.slice_mut(s![.., 1..3, 60..70]).fill(true);
+        b.slice_mut(s![1.., 1..3, 50..65]).fill(true);
+        let (ma, mb) = (BitMask::from_array(&a), BitMask::from_array(&b));
+        assert_eq!(ma.to_array(), a);
+        assert_eq!(ma.count(), 60);
+
+        assert_eq!(ma.count_combined(&mb, None, |x, y| x & y), 20);
+        assert_eq!(ma.count_combined(&mb, None, |x, y| x & !y), 40);
+        assert_eq!(ma.count_combined(&mb, None, |x, y| !x & y), 40);
+        assert_eq!(ma.count_combined(&mb, None, |x, y| !x & !y), 840 - 100);
+
+        let mut roi = Array3::<bool>::from_elem([3, 4, 70], false);
+        roi.slice_mut(s![2, .., ..]).fill(true);
+        assert_eq!(ma.count_combined(&mb, Some(&BitMask::from_array(&roi)), |x, y| x & y), 10);
+    }
+    #[test]
+    fn test_bitmask_edge_across_words() {
+        let mut src = Array3::<bool>::from_elem([5, 5, 130], false);
+        src.slice_mut(s![1..4, 1..4, 60..129]).fill(true);
+        let edge = BitMask::from_array(&src).edge(false).to_array();
+
+        let mut expected = src.clone();
+        expected.slice_mut(s![2..3, 2..3, 61..128]).fill(false);
+        assert_eq!(edge, expected);
+    }
+    #[test]
+    #[should_panic(expected = "roi must have the shape of the masks")]
+    fn test_bitmask_roi_shape() {
+        let mask = BitMask::new([2, 3, 4]);
+        mask.count_combined(&mask, Some(&BitMask::new([2, 3, 5])), |a, b| a & b);
+    }
+}
@@ -0,0 +1,148 @@
+use ndarray::{s, Array, Array3, Ix3, Zip};
+use crate::distance::HausdorffMapDirected;
+use crate::overlap::ConfusionMatrix;
+use crate::utils::{distance_transform, generate_edge, joint_crop};
+
+/// Voxels of `arr` within `width` mm of its own surface. The distance transform only
+/// covers the bounding box of the foreground, which holds every voxel of the band.
+fn inner_band(arr: &Array<bool, Ix3>, zyx_spacing: &[f32], width: f32) -> Array<bool, Ix3> {
+    let mut band = Array3::<bool>::from_elem(arr.raw_dim(), false);
+    if let Some([zs, ys, xs]) = joint_crop(&[arr], 0) {
+        let crop = s![zs, ys, xs];
+        let arr = arr.slice(crop).to_owned();
+        let distances = distance_transform(&generate_edge(&arr, false), zyx_spacing);
+        band.slice_mut(crop).assign(&Zip::from(&arr).and(&distances).map_collect(|v, d| *v & (*d <= width)));
+    }
+    band
+}
+
+/// Boundary IoU (Cheng et al., 2021): IoU of both masks restricted to a band of
+/// `width` mm inside their own contours.
+pub struct BoundaryIoU {
+    ref_arr: Array<bool, Ix3>,
+    other_arr: Array<bool, Ix3>,
+    zyx_spacing: Vec<f32>,
+    width: f32,
+    cm: ConfusionMatrix,
+}
+
+impl BoundaryIoU {
+    pub fn new(ref_arr: &Array<bool, Ix3>, other_arr: &Array<bool, Ix3>, zyx_spacing: Vec<f32>, width: f32) -> BoundaryIoU {
+        BoundaryIoU {
+            ref_arr: ref_arr.clone(),
+            other_arr: other_arr.clone(),
+            zyx_spacing,
+            width,
+            cm: ConfusionMatrix::from_counts(0, 0, 0, 0),
+        }
+    }
+    pub fn execute(&mut self) {
+        let ref_band = inner_band(&self.ref_arr, &self.zyx_spacing, self.width);
+        let other_band = inner_band(&self.other_arr, &self.zyx_spacing, self.width);
+        self.cm = ConfusionMatrix::new(&ref_band, &other_band);
+        self.cm.execute();
+    }
+    pub fn biou(&self) -> f32 {
+        self.cm.jc()
+    }
+}
+
+/// Boundary F-score: harmonic mean of the fraction of the other surface within `tolerance`
+/// of the reference surface (precision) and vice versa (recall).
+pub struct BoundaryFScore {
+    ref_to_other_map: HausdorffMapDirected,
+    other_to_ref_map: HausdorffMapDirected,
+}
+
+impl BoundaryFScore {
+    pub fn new(ref_arr: &Array<bool, Ix3>, other_arr: &Array<bool, Ix3>, zyx_spacing: Vec<f32>) -> BoundaryFScore {
+        BoundaryFScore {
+            ref_to_other_map: HausdorffMapDirected::new(ref_arr, other_arr, zyx_spacing.clone()),
+            other_to_ref_map: HausdorffMapDirected::new(other_arr, ref_arr, zyx_spacing),
+        }
+    }
+    pub fn execute(&mut self) {
+        self.ref_to_other_map.execute();
+        self.other_to_ref_map.execute();
+    }
+    pub fn precision(&self, tolerance: &f32) -> f32 {
+        self.other_to_ref_map.surface_dc(tolerance)
+    }
+    pub fn recall(&self, tolerance: &f32) -> f32 {
+        self.ref_to_other_map.surface_dc(tolerance)
+    }
+    pub fn f_score(&self, tolerance: &f32) -> f32 {
+        let precision = self.precision(tolerance);
+        let recall = self.recall(tolerance);
+        2.0 * precision * recall / (precision + recall)
+    }
+}
+
+#[cfg(test)]
+mod test_boundary {
+    use ndarray::{s, Array, Array3, Ix3};
+    use crate::boundary::{inner_band, BoundaryFScore, BoundaryIoU};
+
+    fn generate_src_dst_arrays() -> (Array<bool, Ix3>, Array<bool, Ix3>) {
+        let mut src = Array3::<bool>::from_elem([1, 12, 12], false);
+        let mut dst = Array3::<bool>::from_elem([1, 12, 12], false);
+        src.slice_mut(s![0, 1..11, 1..11]).fill(true);
+        dst.slice_mut(s![0, 1..11, 1..9]).fill(true);
+        (src, dst)
+    }
+
+    #[test]
+    fn test_boundary_iou() {
+        let (src, dst) = generate_src_dst_arrays();
+        let mut biou = BoundaryIoU::new(&src, &src, vec![1.0, 1.0, 1.0], 1.0);
+        biou.execute();
+        assert_eq!(biou.biou(), 1.0);
+
+        let mut biou = BoundaryIoU::new(&src, &dst, vec![1.0, 1.0, 1.0], 0.0);
+        biou.execute();
+        // A single slice is all boundary, so a zero-width band is the whole mask
+        assert_eq!(biou.biou(), 0.8);
+    }
+    #[test]
+    fn test_boundary_f_score() {
+        let (src, dst) = generate_src_dst_arrays();
+        let mut bf = BoundaryFScore::new(&src, &dst, vec![1.0, 1.0, 1.0]);
+        bf.execute();
+
+        // A single slice is all boundary, so every point of the smaller mask is on the reference
+        assert_eq!(bf.precision(&0.0), 1.0);
+        assert_eq!(bf.recall(&0.0), 0.8);
+        assert_eq!(bf.recall(&2.0), 1.0);
+        assert_eq!(bf.f_score(&2.0), 1.0);
+        assert_eq!(bf.f_score(&0.0), 2.0 * 0.8 / 1.8);
+    }
+    #[test]
+    fn test_boundary_band_3d() {
+        // 5x7x9 box: a shell of 210 voxels around a 3x5x7 interior of 105
+        let mut src = Array3::<bool>::from_elem([7, 9, 11], false);
+        src.slice_mut(s![1..6, 1..8, 1..10]).fill(true);
+        let count = |band: &Array<bool, Ix3>| band.iter().filter(|v| **v).count();
+
+        let band = inner_band(&src, &[2.0, 1.0, 1.0], 0.0);
+        assert_eq!(count(&band), 210);
+        assert!(!band[[3, 4, 5]] & band[[1, 4, 5]]);
+        // 1 mm adds the interior layers next to the y and x faces, but not the z faces (2 mm away)
+        let band = inner_band(&src, &[2.0, 1.0, 1.0], 1.0);
+        assert_eq!(count(&band), 210 + 60);
+        assert!(!band[[2, 4, 5]] & band[[3, 2, 5]]);
+        // 2 mm leaves only the three innermost voxels
+        let band = inner_band(&src, &[2.0, 1.0, 1.0], 2.0);
+        assert_eq!(count(&band), 315 - 3);
+        assert!(!band[[3, 4, 5]]);
+        // With isotropic spacing the z faces are 1 mm away as well
+        let band = inner_band(&src, &[1.0, 1.0, 1.0], 1.0);
+        assert_eq!(count(&band), 315 - 15);
+
+        // Other box ends two voxels earlier along x: shells of 210 and 170 voxels sharing 155
+        let mut dst = Array3::<bool>::from_elem([7, 9, 11], false);
+        dst.slice_mut(s![1..6, 1..8, 1..8]).fill(true);
+        let mut biou = BoundaryIoU::new(&src, &dst, vec![2.0, 1.0, 1.0], 0.0);
+        biou.execute();
+        assert_eq!(biou.biou(), 155.0 / 225.0);
+    }
+}
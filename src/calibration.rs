@@ -0,0 +1,140 @@
+use ndarray::{Array, Ix3};
+
+const EPS: f64 = 1e-7;
+
+/// Calibration of a voxelwise probability map against a boolean reference.
+/// Probabilities are grouped into `n_bins` equal-width bins for the expected
+/// calibration error and the reliability diagram.
+pub struct Calibration {
+    ref_arr: Array<bool, Ix3>,
+    prob_arr: Array<f32, Ix3>,
+    n_bins: usize,
+    roi: Option<Array<bool, Ix3>>,
+    bin_counts: Vec<u64>,
+    bin_prob_sums: Vec<f64>,
+    bin_pos_counts: Vec<u64>,
+    brier_sum: f64,
+    nll_sum: f64,
+}
+
+impl Calibration {
+    pub fn new(ref_arr: &Array<bool, Ix3>, prob_arr: &Array<f32, Ix3>, n_bins: usize) -> Calibration {
+        assert!(n_bins > 0, "n_bins must be positive");
+        assert_eq!(ref_arr.shape(), prob_arr.shape(), "reference and probability map must have the same shape");
+        Calibration {
+            ref_arr: ref_arr.clone(),
+            prob_arr: prob_arr.clone(),
+            n_bins,
+            roi: None,
+            bin_counts: vec![0; n_bins],
+            bin_prob_sums: vec![0.0; n_bins],
+            bin_pos_counts: vec![0; n_bins],
+            brier_sum: 0.0,
+            nll_sum: 0.0,
+        }
+    }
+    /// Only use voxels inside `roi`, e.g. a band from `utils::surface_band`.
+    pub fn with_roi(mut self, roi: &Array<bool, Ix3>) -> Calibration {
+        assert_eq!(self.ref_arr.shape(), roi.shape(), "roi must have the shape of the reference");
+        self.roi = Some(roi.clone());
+        self
+    }
+    pub fn execute(&mut self) {
+        for ((idx, ref_val), prob) in self.ref_arr.indexed_iter().zip(self.prob_arr.iter()) {
+            if let Some(roi) = &self.roi {
+                if !roi[idx] { continue; }
+            }
+            let prob = *prob as f64;
+            let target = if *ref_val { 1.0 } else { 0.0 };
+            let bin = ((prob * self.n_bins as f64).floor() as usize).min(self.n_bins - 1);
+
+            self.bin_counts[bin] += 1;
+            self.bin_prob_sums[bin] += prob;
+            if *ref_val {
+                self.bin_pos_counts[bin] += 1;
+            }
+            self.brier_sum += (prob - target).powi(2);
+            let prob = prob.clamp(EPS, 1.0 - EPS);
+            self.nll_sum -= target * prob.ln() + (1.0 - target) * (1.0 - prob).ln();
+        }
+    }
+    fn n_voxels(&self) -> u64 {
+        self.bin_counts.iter().sum()
+    }
+    /// Expected calibration error: count-weighted mean of |accuracy - confidence| over bins.
+    pub fn ece(&self) -> f32 {
+        let n = self.n_voxels() as f64;
+        let mut ece = 0.0;
+        for b in 0..self.n_bins {
+            if self.bin_counts[b] == 0 {
+                continue;
+            }
+            let count = self.bin_counts[b] as f64;
+            let gap = self.bin_pos_counts[b] as f64 / count - self.bin_prob_sums[b] / count;
+            ece += count / n * gap.abs();
+        }
+        ece as f32
+    }
+    pub fn brier(&self) -> f32 {
+        (self.brier_sum / self.n_voxels() as f64) as f32
+    }
+    pub fn nll(&self) -> f32 {
+        (self.nll_sum / self.n_voxels() as f64) as f32
+    }
+    /// Number of voxels per bin.
+    pub fn bin_counts(&self) -> Vec<u64> {
+        self.bin_counts.clone()
+    }
+    /// Mean predicted probability per bin (NaN for empty bins).
+    pub fn bin_confidence(&self) -> Vec<f32> {
+        self.bin_prob_sums.iter().zip(self.bin_counts.iter())
+            .map(|(sum, count)| (sum / *count as f64) as f32)
+            .collect()
+    }
+    /// Observed fraction of reference voxels per bin (NaN for empty bins).
+    pub fn bin_accuracy(&self) -> Vec<f32> {
+        self.bin_pos_counts.iter().zip(self.bin_counts.iter())
+            .map(|(pos, count)| (*pos as f64 / *count as f64) as f32)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test_calibration {
+    use ndarray::Array3;
+    use crate::calibration::Calibration;
+
+    #[test]
+    fn test_calibration() {
+        let src = Array3::<u8>::from(vec![[[1, 1, 0, 0]]]).mapv(|x| x != 0);
+        let prob = Array3::<f32>::from(vec![[[1.0, 0.5, 0.5, 0.0]]]);
+
+        let mut calibration = Calibration::new(&src, &prob, 2);
+        calibration.execute();
+
+        assert_eq!(calibration.bin_counts(), vec![1, 3]);
+        assert_eq!(calibration.bin_confidence(), vec![0.0, 2.0 / 3.0]);
+        assert_eq!(calibration.bin_accuracy(), vec![0.0, 2.0 / 3.0]);
+        assert_eq!(calibration.ece(), 0.0);
+        assert_eq!(calibration.brier(), 0.125);
+        assert!((calibration.nll() - 0.5 * 2.0_f32.ln()).abs() < 1e-6);
+    }
+    #[test]
+    fn test_calibration_roi() {
+        let src = Array3::<u8>::from(vec![[[1, 1, 0, 0]]]).mapv(|x| x != 0);
+        let prob = Array3::<f32>::from(vec![[[1.0, 0.5, 0.5, 0.0]]]);
+        let roi = Array3::<u8>::from(vec![[[0, 1, 1, 0]]]).mapv(|x| x != 0);
+
+        let mut calibration = Calibration::new(&src, &prob, 4).with_roi(&roi);
+        calibration.execute();
+
+        assert_eq!(calibration.bin_counts(), vec![0, 0, 2, 0]);
+        assert_eq!(calibration.ece(), 0.0);
+        assert_eq!(calibration.brier(), 0.25);
+    }
+    #[test]
+    #[should_panic(expected = "reference and probability map must have the same shape")]
+    fn test_shape_mismatch() {
+        Calibration::new(&Array3::<bool>::from_elem([2, 2, 2], false), &Array3::<f32>::zeros([2, 2, 3]), 10);
+    }
+}
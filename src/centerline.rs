@@ -0,0 +1,104 @@
+use ndarray::{Array, Ix3, Zip};
+use crate::utils::{centerline_length, skeletonize};
+
+/// Centerline Dice (Shit et al., 2021) and related skeleton-based metrics for tubular
+/// structures. Both masks are thinned with `utils::skeletonize`.
+pub struct ClDice {
+    ref_arr: Array<bool, Ix3>,
+    other_arr: Array<bool, Ix3>,
+    zyx_spacing: Vec<f32>,
+    ref_skeleton: Array<bool, Ix3>,
+    other_skeleton: Array<bool, Ix3>,
+}
+
+/// Fraction of the skeleton voxels that lie inside `mask`.
+fn skeleton_fraction_inside(skeleton: &Array<bool, Ix3>, mask: &Array<bool, Ix3>) -> f32 {
+    let mut inside = 0_u32;
+    let mut total = 0_u32;
+    Zip::from(skeleton).and(mask).for_each(|s, m| {
+        if *s {
+            total += 1;
+            inside += u32::from(*m);
+        }
+    });
+    inside as f32 / total as f32
+}
+
+impl ClDice {
+    pub fn new(ref_arr: &Array<bool, Ix3>, other_arr: &Array<bool, Ix3>, zyx_spacing: Vec<f32>) -> ClDice {
+        ClDice {
+            ref_arr: ref_arr.clone(),
+            other_arr: other_arr.clone(),
+            zyx_spacing,
+            ref_skeleton: Array::from_elem(ref_arr.raw_dim(), false),
+            other_skeleton: Array::from_elem(other_arr.raw_dim(), false),
+        }
+    }
+    pub fn execute(&mut self) {
+        self.ref_skeleton = skeletonize(&self.ref_arr);
+        self.other_skeleton = skeletonize(&self.other_arr);
+    }
+    /// Fraction of the other skeleton inside the reference mask.
+    pub fn topology_precision(&self) -> f32 {
+        skeleton_fraction_inside(&self.other_skeleton, &self.ref_arr)
+    }
+    /// Fraction of the reference skeleton inside the other mask.
+    pub fn topology_sensitivity(&self) -> f32 {
+        skeleton_fraction_inside(&self.ref_skeleton, &self.other_arr)
+    }
+    pub fn cl_dice(&self) -> f32 {
+        let precision = self.topology_precision();
+        let sensitivity = self.topology_sensitivity();
+        2.0 * precision * sensitivity / (precision + sensitivity)
+    }
+    /// Centerline length of the reference in mm.
+    pub fn ref_length(&self) -> f32 {
+        centerline_length(&self.ref_skeleton, &self.zyx_spacing)
+    }
+    /// Centerline length of the other mask in mm.
+    pub fn other_length(&self) -> f32 {
+        centerline_length(&self.other_skeleton, &self.zyx_spacing)
+    }
+    /// Other centerline length minus reference centerline length, in mm.
+    pub fn length_difference(&self) -> f32 {
+        self.other_length() - self.ref_length()
+    }
+    pub fn ref_skeleton(&self) -> &Array<bool, Ix3> {
+        &self.ref_skeleton
+    }
+    pub fn other_skeleton(&self) -> &Array<bool, Ix3> {
+        &self.other_skeleton
+    }
+}
+
+#[cfg(test)]
+mod test_centerline {
+    use ndarray::Array3;
+    use crate::centerline::ClDice;
+
+    #[test]
+    fn test_cl_dice() {
+        let mut src = Array3::<bool>::from_elem([5, 5, 14], false);
+        let mut dst = Array3::<bool>::from_elem([5, 5, 14], false);
+        for z in 1..4 {
+            for y in 1..4 {
+                for x in 1..13 {
+                    src[[z, y, x]] = true;
+                    // Broken vessel: the other mask misses a segment in the middle
+                    dst[[z, y, x]] = !(6..=7).contains(&x);
+                }
+            }
+        }
+        let mut cl = ClDice::new(&src, &src, vec![1.0, 1.0, 1.0]);
+        cl.execute();
+        assert_eq!(cl.cl_dice(), 1.0);
+        assert_eq!(cl.length_difference(), 0.0);
+
+        let mut cl = ClDice::new(&src, &dst, vec![1.0, 1.0, 1.0]);
+        cl.execute();
+        assert_eq!(cl.topology_precision(), 1.0);
+        assert!(cl.topology_sensitivity() < 1.0);
+        assert!(cl.cl_dice() < 1.0);
+        assert!(cl.length_difference() < 0.0);
+    }
+}
@@ -0,0 +1,168 @@
+use ndarray::{Array, Array3, Ix3, Zip};
+
+/// Panics unless there is at least one mask and all masks have the same shape.
+fn check_masks(masks: &[Array<bool, Ix3>]) {
+    assert!(!masks.is_empty(), "need at least one mask");
+    assert!(masks.iter().all(|m| m.shape() == masks[0].shape()), "all masks must have the same shape");
+}
+
+/// Consensus mask where at least `min_votes` of the raters marked the voxel.
+pub fn threshold_vote(masks: &[Array<bool, Ix3>], min_votes: usize) -> Array<bool, Ix3> {
+    check_masks(masks);
+    let mut votes = Array3::<usize>::zeros(masks[0].raw_dim());
+    for mask in masks {
+        Zip::from(&mut votes).and(mask).for_each(|v, m| *v += usize::from(*m));
+    }
+    votes.mapv(|v| v >= min_votes)
+}
+
+/// Consensus mask where a strict majority of the raters marked the voxel.
+pub fn majority_vote(masks: &[Array<bool, Ix3>]) -> Array<bool, Ix3> {
+    threshold_vote(masks, masks.len() / 2 + 1)
+}
+
+/// STAPLE (Warfield et al., 2004) for binary segmentations. Estimates a probabilistic
+/// true segmentation together with each rater's sensitivity and specificity by
+/// expectation-maximisation.
+pub struct Staple {
+    /// Flattened votes of each rater.
+    votes: Vec<Vec<bool>>,
+    max_iter: usize,
+    tolerance: f32,
+    probabilities: Array<f32, Ix3>,
+    sensitivity: Vec<f32>,
+    specificity: Vec<f32>,
+    n_iter: usize,
+}
+
+impl Staple {
+    pub fn new(masks: &[Array<bool, Ix3>], max_iter: usize, tolerance: f32) -> Staple {
+        check_masks(masks);
+        Staple {
+            votes: masks.iter().map(|mask| mask.iter().cloned().collect()).collect(),
+            max_iter,
+            tolerance,
+            probabilities: Array3::<f32>::zeros(masks[0].raw_dim()),
+            sensitivity: vec![0.99; masks.len()],
+            specificity: vec![0.99; masks.len()],
+            n_iter: 0,
+        }
+    }
+    pub fn execute(&mut self) {
+        let votes = &self.votes;
+        let n_raters = votes.len();
+        let n_voxels = self.probabilities.len() as f64;
+        let positive_votes: usize = votes.iter().map(|v| v.iter().filter(|v| **v).count()).sum();
+        let prior = positive_votes as f64 / (n_voxels * n_raters as f64);
+
+        let mut weights = vec![0.0_f64; self.probabilities.len()];
+        for _ in 0..self.max_iter {
+            self.n_iter += 1;
+            // E-step: probability that each voxel is truly foreground
+            for (i, w) in weights.iter_mut().enumerate() {
+                let mut a = prior;
+                let mut b = 1.0 - prior;
+                for (j, rater_votes) in votes.iter().enumerate() {
+                    let p = self.sensitivity[j] as f64;
+                    let q = self.specificity[j] as f64;
+                    if rater_votes[i] {
+                        a *= p;
+                        b *= 1.0 - q;
+                    } else {
+                        a *= 1.0 - p;
+                        b *= q;
+                    }
+                }
+                *w = if a + b > 0.0 { a / (a + b) } else { 0.0 };
+            }
+            // M-step: rater performance given the current estimate
+            let weight_sum: f64 = weights.iter().sum();
+            let mut max_change: f32 = 0.0;
+            for (j, rater_votes) in votes.iter().enumerate() {
+                let mut tp = 0.0_f64;
+                let mut tn = 0.0_f64;
+                for (i, w) in weights.iter().enumerate() {
+                    if rater_votes[i] {
+                        tp += w;
+                    } else {
+                        tn += 1.0 - w;
+                    }
+                }
+                let p = if weight_sum > 0.0 { (tp / weight_sum) as f32 } else { 0.0 };
+                let q = if n_voxels - weight_sum > 0.0 { (tn / (n_voxels - weight_sum)) as f32 } else { 0.0 };
+                max_change = max_change
+                    .max((p - self.sensitivity[j]).abs())
+                    .max((q - self.specificity[j]).abs());
+                self.sensitivity[j] = p;
+                self.specificity[j] = q;
+            }
+            if max_change < self.tolerance {
+                break;
+            }
+        }
+        for (prob, w) in self.probabilities.iter_mut().zip(weights.iter()) {
+            *prob = *w as f32;
+        }
+    }
+    /// Probabilistic estimate of the true segmentation.
+    pub fn probabilities(&self) -> &Array<f32, Ix3> {
+        &self.probabilities
+    }
+    /// Consensus mask: voxels with estimated probability of at least 0.5.
+    pub fn consensus(&self) -> Array<bool, Ix3> {
+        self.probabilities.mapv(|p| p >= 0.5)
+    }
+    pub fn sensitivity(&self) -> &[f32] {
+        &self.sensitivity
+    }
+    pub fn specificity(&self) -> &[f32] {
+        &self.specificity
+    }
+    pub fn n_iter(&self) -> usize {
+        self.n_iter
+    }
+}
+
+#[cfg(test)]
+mod test_consensus {
+    use ndarray::{Array, Array3, Ix3};
+    use crate::consensus::{majority_vote, threshold_vote, Staple};
+
+    fn generate_rater_arrays() -> Vec<Array<bool, Ix3>> {
+        let a = Array3::<u8>::from(vec![[[1, 1, 1, 0, 0, 0]]]);
+        let b = Array3::<u8>::from(vec![[[1, 1, 1, 1, 0, 0]]]);
+        let c = Array3::<u8>::from(vec![[[0, 1, 1, 0, 0, 1]]]);
+        vec![a.mapv(|x| x != 0), b.mapv(|x| x != 0), c.mapv(|x| x != 0)]
+    }
+
+    #[test]
+    fn test_voting() {
+        let masks = generate_rater_arrays();
+        let majority = Array3::<u8>::from(vec![[[1, 1, 1, 0, 0, 0]]]).mapv(|x| x != 0);
+        let union = Array3::<u8>::from(vec![[[1, 1, 1, 1, 0, 1]]]).mapv(|x| x != 0);
+
+        assert!(majority_vote(&masks) == majority);
+        assert!(threshold_vote(&masks, 1) == union);
+    }
+    #[test]
+    fn test_staple() {
+        let masks = generate_rater_arrays();
+        let mut staple = Staple::new(&masks, 100, 1e-6);
+        staple.execute();
+
+        assert!(staple.consensus() == majority_vote(&masks));
+        assert!(staple.n_iter() <= 100);
+        assert!(staple.probabilities().iter().all(|p| (0.0..=1.0).contains(p)));
+        assert_eq!(staple.sensitivity().len(), 3);
+        // The first rater agrees with the consensus everywhere
+        assert!((staple.sensitivity()[0] - 1.0).abs() < 1e-3);
+        assert!((staple.specificity()[0] - 1.0).abs() < 1e-3);
+        assert!(staple.specificity()[2] < staple.specificity()[0]);
+    }
+    #[test]
+    #[should_panic(expected = "all masks must have the same shape")]
+    fn test_shape_mismatch() {
+        let masks = vec![Array3::<bool>::from_elem([2, 2, 2], false), Array3::<bool>::from_elem([2, 2, 3], false)];
+        Staple::new(&masks, 10, 1e-5);
+    }
+}
@@ -0,0 +1,136 @@
+use ndarray::{Array, Ix3};
+use crate::distance::{DistanceBackend, HausdorffMapDirected};
+
+/// Directions used to bin surface distances, in index order: +z, -z, +y, -y, +x, -x.
+pub const DIRECTIONS: [&str; 6] = ["+z", "-z", "+y", "-y", "+x", "-x"];
+
+/// Directed surface distances from the reference to another mask, binned by the
+/// dominant axis of the vector to the nearest point on the other surface. A reference
+/// surface point counts as over-segmented when it lies inside the other mask (the other
+/// surface is further out) and under-segmented otherwise. Points at distance 0 and points
+/// without a nearest point (empty other mask) are not binned.
+pub struct DirectionalSurfaceDistance {
+    hd_map: HausdorffMapDirected,
+    other_arr: Array<bool, Ix3>,
+    counts: [u32; 6],
+    sums: [f32; 6],
+    maxima: [f32; 6],
+    over_counts: [u32; 6],
+    under_counts: [u32; 6],
+}
+
+fn direction_bin(displacement: &[f32; 3]) -> usize {
+    let mut axis = 0;
+    for i in 1..3 {
+        if displacement[i].abs() > displacement[axis].abs() {
+            axis = i;
+        }
+    }
+    2 * axis + usize::from(displacement[axis] < 0.0)
+}
+
+impl DirectionalSurfaceDistance {
+    pub fn new(ref_arr: &Array<bool, Ix3>, other_arr: &Array<bool, Ix3>, zyx_spacing: Vec<f32>) -> DirectionalSurfaceDistance {
+        DirectionalSurfaceDistance {
+            hd_map: HausdorffMapDirected::new(ref_arr, other_arr, zyx_spacing).with_backend(DistanceBackend::KdTree),
+            other_arr: other_arr.clone(),
+            counts: [0; 6],
+            sums: [0.0; 6],
+            maxima: [0.0; 6],
+            over_counts: [0; 6],
+            under_counts: [0; 6],
+        }
+    }
+    pub fn execute(&mut self) {
+        self.hd_map.execute();
+        let points = self.hd_map.ref_points();
+        let displacements = self.hd_map.displacements();
+        for ((point, displacement), dist) in points.iter().zip(displacements.iter()).zip(self.hd_map.distances()) {
+            // Skip points without a nearest other point (empty other surface)
+            if (*dist == 0.0) | !dist.is_finite() | (*dist >= 999999.0) | !displacement.iter().all(|d| d.is_finite()) {
+                continue;
+            }
+            let bin = direction_bin(displacement);
+            self.counts[bin] += 1;
+            self.sums[bin] += dist;
+            self.maxima[bin] = self.maxima[bin].max(*dist);
+            if self.other_arr[*point] {
+                self.over_counts[bin] += 1;
+            } else {
+                self.under_counts[bin] += 1;
+            }
+        }
+    }
+    /// Number of displaced reference surface points per direction.
+    pub fn counts(&self) -> [u32; 6] {
+        self.counts
+    }
+    /// Mean distance per direction (NaN where no points fall in a direction).
+    pub fn mean(&self) -> [f32; 6] {
+        [0, 1, 2, 3, 4, 5].map(|i| self.sums[i] / self.counts[i] as f32)
+    }
+    /// Maximum distance per direction (0 where no points fall in a direction).
+    pub fn max(&self) -> [f32; 6] {
+        self.maxima
+    }
+    pub fn over_counts(&self) -> [u32; 6] {
+        self.over_counts
+    }
+    pub fn under_counts(&self) -> [u32; 6] {
+        self.under_counts
+    }
+}
+
+#[cfg(test)]
+mod test_directional {
+    use ndarray::{s, Array3};
+    use crate::directional::DirectionalSurfaceDistance;
+
+    #[test]
+    fn test_directional_surface_distance() {
+        // Other mask is the reference grown by two voxels towards +x
+        let mut src = Array3::<bool>::from_elem([5, 7, 10], false);
+        let mut dst = Array3::<bool>::from_elem([5, 7, 10], false);
+        for z in 1..4 {
+            for y in 1..6 {
+                for x in 1..5 {
+                    src[[z, y, x]] = true;
+                }
+                for x in 1..7 {
+                    dst[[z, y, x]] = true;
+                }
+            }
+        }
+        let mut directional = DirectionalSurfaceDistance::new(&src, &dst, vec![10.0, 10.0, 1.0]);
+        directional.execute();
+
+        assert_eq!(directional.counts(), [0, 0, 0, 0, 3, 0]);
+        assert_eq!(directional.max()[4], 2.0);
+        assert_eq!(directional.mean()[4], 2.0);
+        assert!(directional.mean()[5].is_nan());
+        assert_eq!(directional.over_counts()[4], 3);
+        assert_eq!(directional.under_counts(), [0; 6]);
+
+        let mut directional = DirectionalSurfaceDistance::new(&dst, &src, vec![10.0, 10.0, 1.0]);
+        directional.execute();
+        let counts = directional.counts();
+        assert!(counts[5] > 0);
+        assert_eq!(counts.iter().sum::<u32>(), counts[5]);
+        assert_eq!(directional.under_counts(), counts);
+        assert_eq!(directional.max()[5], 2.0);
+    }
+    #[test]
+    fn test_empty_prediction() {
+        let mut src = Array3::<bool>::from_elem([5, 5, 5], false);
+        src.slice_mut(s![1..4, 1..4, 1..4]).fill(true);
+        let empty = Array3::<bool>::from_elem([5, 5, 5], false);
+
+        let mut directional = DirectionalSurfaceDistance::new(&src, &empty, vec![1.0, 1.0, 1.0]);
+        directional.execute();
+        assert_eq!(directional.counts(), [0; 6]);
+        assert!(directional.mean().iter().all(|m| m.is_nan()));
+        assert_eq!(directional.max(), [0.0; 6]);
+        assert_eq!(directional.over_counts(), [0; 6]);
+        assert_eq!(directional.under_counts(), [0; 6]);
+    }
+}
@@ -0,0 +1,89 @@
+use ndarray::{Array, Ix3};
+use crate::shape::ShapeDescriptors;
+
+/// Systematic shift of another mask relative to the reference: centre-of-mass
+/// displacement and per-axis boundary and extent differences, all in mm (zyx order).
+/// All values are NaN when either mask is empty.
+pub struct CentroidDisplacement {
+    ref_shape: ShapeDescriptors,
+    other_shape: ShapeDescriptors,
+}
+
+impl CentroidDisplacement {
+    pub fn new(ref_arr: &Array<bool, Ix3>, other_arr: &Array<bool, Ix3>, zyx_spacing: Vec<f32>) -> CentroidDisplacement {
+        CentroidDisplacement {
+            ref_shape: ShapeDescriptors::new(ref_arr, zyx_spacing.clone()),
+            other_shape: ShapeDescriptors::new(other_arr, zyx_spacing),
+        }
+    }
+    pub fn execute(&mut self) {
+        self.ref_shape.execute();
+        self.other_shape.execute();
+    }
+    /// Other centroid minus reference centroid.
+    pub fn displacement(&self) -> [f32; 3] {
+        let ref_centroid = self.ref_shape.centroid();
+        let other_centroid = self.other_shape.centroid();
+        [0, 1, 2].map(|i| other_centroid[i] - ref_centroid[i])
+    }
+    /// Euclidean distance between the centroids.
+    pub fn distance(&self) -> f32 {
+        self.displacement().iter().map(|d| d.powi(2)).sum::<f32>().sqrt()
+    }
+    /// Other extent minus reference extent.
+    pub fn extent_difference(&self) -> [f32; 3] {
+        let ref_extent = self.ref_shape.extent();
+        let other_extent = self.other_shape.extent();
+        [0, 1, 2].map(|i| other_extent[i] - ref_extent[i])
+    }
+    /// Shift of the low-index and high-index boundary along each axis (other minus reference),
+    /// e.g. the inferior and superior border along z for a feet-first index order.
+    pub fn boundary_shift(&self) -> [[f32; 2]; 3] {
+        let ref_bounds = self.ref_shape.bounds();
+        let other_bounds = self.other_shape.bounds();
+        [0, 1, 2].map(|i| [other_bounds[i][0] - ref_bounds[i][0], other_bounds[i][1] - ref_bounds[i][1]])
+    }
+}
+
+#[cfg(test)]
+mod test_displacement {
+    use ndarray::Array3;
+    use crate::displacement::CentroidDisplacement;
+
+    #[test]
+    fn test_centroid_displacement() {
+        let mut src = Array3::<bool>::from_elem([6, 6, 6], false);
+        let mut dst = Array3::<bool>::from_elem([6, 6, 6], false);
+        for z in 1..3 {
+            for y in 1..3 {
+                for x in 1..3 {
+                    src[[z, y, x]] = true;
+                    dst[[z, y + 1, x + 2]] = true;
+                    dst[[z + 1, y + 1, x + 2]] = true;
+                }
+            }
+        }
+        let mut displacement = CentroidDisplacement::new(&src, &dst, vec![3.0, 2.0, 1.0]);
+        displacement.execute();
+
+        assert_eq!(displacement.displacement(), [1.5, 2.0, 2.0]);
+        assert_eq!(displacement.distance(), (1.5_f32.powi(2) + 8.0).sqrt());
+        assert_eq!(displacement.extent_difference(), [3.0, 0.0, 0.0]);
+        assert_eq!(displacement.boundary_shift(), [[0.0, 3.0], [2.0, 2.0], [2.0, 2.0]]);
+    }
+    #[test]
+    fn test_empty_mask() {
+        let mut src = Array3::<bool>::from_elem([4, 4, 4], false);
+        src[[1, 1, 1]] = true;
+        let empty = Array3::<bool>::from_elem([4, 4, 4], false);
+
+        for (ref_arr, other_arr) in [(&src, &empty), (&empty, &src), (&empty, &empty)] {
+            let mut displacement = CentroidDisplacement::new(ref_arr, other_arr, vec![1.0, 1.0, 1.0]);
+            displacement.execute();
+            assert!(displacement.displacement().iter().all(|d| d.is_nan()));
+            assert!(displacement.distance().is_nan());
+            assert!(displacement.extent_difference().iter().all(|d| d.is_nan()));
+            assert!(displacement.boundary_shift().iter().flatten().all(|d| d.is_nan()));
+        }
+    }
+}
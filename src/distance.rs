@@ -1,5 +1,6 @@
-use ndarray::{Array, Ix3};
-use crate::utils::generate_edge;
+use ndarray::{Array, Array3, Ix3};
+use crate::kdtree::KdTree;
+use crate::utils::{distance_transform, generate_edge, joint_crop, restrict_to_slices};
 
 struct CoordComponent {
     idx: u16,
@@ -7,7 +8,7 @@ struct CoordComponent {
 }
 impl CoordComponent {
     fn physical_point(&self) -> f32 {
-        f32::from(self.idx) * &self.spacing
+        f32::from(self.idx) * self.spacing
     }
 }
 struct Coord {
@@ -17,22 +18,41 @@ struct Coord {
 }
 impl Coord{
     fn distance_to(&self, other_coord: &Coord) -> f32 {
-        let z_delta: f32 = other_coord.z.physical_point() - &self.z.physical_point();
-        let y_delta: f32 = other_coord.y.physical_point() - &self.y.physical_point();
-        let x_delta: f32 = other_coord.x.physical_point() - &self.x.physical_point();
-        let dist = &z_delta.powf(2.0) + &y_delta.powf(2.0) + &x_delta.powf(2.0);
-        let dist = dist.sqrt();
-        dist
+        let z_delta: f32 = other_coord.z.physical_point() - self.z.physical_point();
+        let y_delta: f32 = other_coord.y.physical_point() - self.y.physical_point();
+        let x_delta: f32 = other_coord.x.physical_point() - self.x.physical_point();
+        let dist = z_delta.powf(2.0) + y_delta.powf(2.0) + x_delta.powf(2.0);
+        dist.sqrt()
     }
 }
 
+/// How the nearest other surface point is found for every reference surface point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DistanceBackend {
+    /// Compare every pair of surface points.
+    BruteForce,
+    /// Nearest-neighbour queries on a k-d tree of the other surface points.
+    KdTree,
+    /// Distance transform of the other surface over the joint bounding box of both
+    /// surfaces. Distances match the other backends up to floating point rounding;
+    /// nearest points are not recorded, so `displacements` looks them up in a k-d tree.
+    DistanceTransform,
+    /// `KdTree` or `DistanceTransform`, whichever is estimated to be cheaper.
+    Auto,
+}
+
 pub struct HausdorffMapDirected {
     ref_arr: Array<bool, Ix3>,
     other_arr: Array<bool, Ix3>,
     zyx_spacing: Vec<f32>,
     ref_coords: Vec<Coord>,
     other_coords: Vec<Coord>,
-    distances: Vec<f32>
+    distances: Vec<f32>,
+    nearest: Vec<usize>,
+    roi: Option<Array<bool, Ix3>>,
+    slices: Vec<(Vec<usize>, usize)>,
+    in_plane: bool,
+    backend: DistanceBackend,
 }
 
 impl HausdorffMapDirected {
@@ -40,19 +60,104 @@ impl HausdorffMapDirected {
            other_arr: &Array<bool, Ix3>,
            zyx_spacing: Vec<f32>) -> HausdorffMapDirected {
         HausdorffMapDirected {
-            ref_arr: generate_edge(ref_arr, false),
-            other_arr: generate_edge(other_arr, false),
+            ref_arr: ref_arr.clone(),
+            other_arr: other_arr.clone(),
             zyx_spacing,
             ref_coords: Vec::new(),
             other_coords: Vec::new(),
             distances: Vec::new(),
+            nearest: Vec::new(),
+            roi: None,
+            slices: Vec::new(),
+            in_plane: false,
+            backend: DistanceBackend::Auto,
         }
     }
-    pub fn execute(&mut self) {
-        for z in 0..self.ref_arr.shape()[0] {
-            for y in 0..self.ref_arr.shape()[1] {
-                for x in 0..self.ref_arr.shape()[2] {
-                    if *self.ref_arr.get([z, y, x]).unwrap() {
+    /// Like `new`, but edges are extracted in-plane (per z-slice), as needed for 2D contours.
+    pub fn new_2d(ref_arr: &Array<bool, Ix3>,
+           other_arr: &Array<bool, Ix3>,
+           zyx_spacing: Vec<f32>) -> HausdorffMapDirected {
+        HausdorffMapDirected {
+            ref_arr: ref_arr.clone(),
+            other_arr: other_arr.clone(),
+            zyx_spacing,
+            ref_coords: Vec::new(),
+            other_coords: Vec::new(),
+            distances: Vec::new(),
+            nearest: Vec::new(),
+            roi: None,
+            slices: Vec::new(),
+            in_plane: true,
+            backend: DistanceBackend::Auto,
+        }
+    }
+    /// Map from precomputed distances of each reference surface point to the other surface.
+    pub fn from_distances(distances: Vec<f32>) -> HausdorffMapDirected {
+        HausdorffMapDirected {
+            ref_arr: Array::from_elem((0, 0, 0), false),
+            other_arr: Array::from_elem((0, 0, 0), false),
+            zyx_spacing: Vec::new(),
+            ref_coords: Vec::new(),
+            other_coords: Vec::new(),
+            distances,
+            nearest: Vec::new(),
+            roi: None,
+            slices: Vec::new(),
+            in_plane: false,
+            backend: DistanceBackend::Auto,
+        }
+    }
+    /// Discard surface points of both structures that fall outside `roi`.
+    /// An ROI that is already set is intersected with `roi`.
+    pub fn with_roi(mut self, roi: &Array<bool, Ix3>) -> HausdorffMapDirected {
+        self.roi = Some(match self.roi {
+            Some(current) => current & roi,
+            None => roi.clone(),
+        });
+        self
+    }
+    /// Discard surface points of both structures that fall inside `ignore`.
+    pub fn with_ignore(self, ignore: &Array<bool, Ix3>) -> HausdorffMapDirected {
+        self.with_roi(&ignore.mapv(|v| !v))
+    }
+    /// Only keep the listed slices along `axis`, e.g. from `reference_slices`. Both masks are
+    /// cut to these slices before their surfaces are extracted, so a structure that continues
+    /// past the last slice gets a surface there, just like one that ends on it.
+    pub fn with_slices(mut self, slices: &[usize], axis: usize) -> HausdorffMapDirected {
+        self.slices.push((slices.to_vec(), axis));
+        self
+    }
+    fn surface(&self, arr: &Array<bool, Ix3>) -> Array<bool, Ix3> {
+        if self.slices.is_empty() {
+            return generate_edge(arr, self.in_plane);
+        }
+        let mut restricted = arr.clone();
+        for (slices, axis) in &self.slices {
+            restricted = restrict_to_slices(&restricted, slices, *axis);
+        }
+        generate_edge(&restricted, self.in_plane)
+    }
+    pub fn with_backend(mut self, backend: DistanceBackend) -> HausdorffMapDirected {
+        self.backend = backend;
+        self
+    }
+    fn collect_coords(&mut self) {
+        if !(self.ref_coords.is_empty() & self.other_coords.is_empty()) {
+            return;
+        }
+        let ref_edge = self.surface(&self.ref_arr);
+        let other_edge = self.surface(&self.other_arr);
+        let [zs, ys, xs] = match joint_crop(&[&ref_edge, &other_edge], 0) {
+            Some(crop) => crop,
+            None => return,
+        };
+        for z in zs {
+            for y in ys.clone() {
+                for x in xs.clone() {
+                    if let Some(roi) = &self.roi {
+                        if !roi[[z, y, x]] { continue; }
+                    }
+                    if ref_edge[[z, y, x]] {
                         let _ = &self.ref_coords.push(
                             Coord {
                                 z: CoordComponent { idx: z as u16, spacing: self.zyx_spacing[0] },
@@ -60,7 +165,7 @@ impl HausdorffMapDirected {
                                 x: CoordComponent { idx: x as u16, spacing: self.zyx_spacing[2] },
                             });
                     }
-                    if *self.other_arr.get([z, y, x]).unwrap() {
+                    if other_edge[[z, y, x]] {
                         let _ = &self.other_coords.push(
                             Coord {
                                 z: CoordComponent { idx: z as u16, spacing: self.zyx_spacing[0] },
@@ -71,19 +176,153 @@ impl HausdorffMapDirected {
                 }
             }
         }
+    }
+    fn physical_points(coords: &[Coord]) -> Vec<[f32; 3]> {
+        coords.iter()
+            .map(|c| [c.z.physical_point(), c.y.physical_point(), c.x.physical_point()])
+            .collect()
+    }
+    /// Backend to use when `Auto`: a distance transform costs a few passes over the joint
+    /// bounding box of both surfaces, a k-d tree a logarithmic query per reference point.
+    fn resolve_backend(&self) -> DistanceBackend {
+        if self.backend != DistanceBackend::Auto {
+            return self.backend;
+        }
+        let bbox_volume: usize = (0..3)
+            .map(|i| {
+                let idx = self.ref_coords.iter().chain(self.other_coords.iter())
+                    .map(|c| [c.z.idx, c.y.idx, c.x.idx][i] as usize);
+                let (min, max) = idx.fold((usize::MAX, 0), |(min, max), v| (min.min(v), max.max(v)));
+                max.saturating_sub(min) + 1
+            })
+            .product();
+        let n_other = self.other_coords.len() as f64;
+        let kd_cost = (self.ref_coords.len() as f64 + n_other) * (n_other + 1.0).log2() * 4.0;
+        if (bbox_volume as f64 * 3.0) < kd_cost {
+            DistanceBackend::DistanceTransform
+        } else {
+            DistanceBackend::KdTree
+        }
+    }
+    pub fn execute(&mut self) {
+        self.collect_coords();
+        if self.ref_coords.is_empty() {
+            return;
+        }
+        if self.other_coords.is_empty() {
+            self.distances = vec![999999.0; self.ref_coords.len()];
+            self.nearest = vec![0; self.ref_coords.len()];
+            return;
+        }
+        match self.resolve_backend() {
+            DistanceBackend::BruteForce | DistanceBackend::Auto => self.execute_brute_force(),
+            DistanceBackend::KdTree => {
+                let tree = KdTree::new(Self::physical_points(&self.other_coords));
+                for point in Self::physical_points(&self.ref_coords) {
+                    let (idx, dist) = tree.nearest(&point).unwrap();
+                    self.distances.push(dist);
+                    self.nearest.push(idx);
+                }
+            }
+            DistanceBackend::DistanceTransform => {
+                let all = || self.ref_coords.iter().chain(self.other_coords.iter());
+                let min = [0, 1, 2].map(|i| all().map(|c| [c.z.idx, c.y.idx, c.x.idx][i] as usize).min().unwrap());
+                let max = [0, 1, 2].map(|i| all().map(|c| [c.z.idx, c.y.idx, c.x.idx][i] as usize).max().unwrap());
+                let mut other = Array3::<bool>::from_elem((max[0] - min[0] + 1, max[1] - min[1] + 1, max[2] - min[2] + 1), false);
+                for c in &self.other_coords {
+                    other[[c.z.idx as usize - min[0], c.y.idx as usize - min[1], c.x.idx as usize - min[2]]] = true;
+                }
+                let dt = distance_transform(&other, &self.zyx_spacing);
+                self.distances = self.ref_coords.iter()
+                    .map(|c| dt[[c.z.idx as usize - min[0], c.y.idx as usize - min[1], c.x.idx as usize - min[2]]])
+                    .collect();
+            }
+        }
+    }
+    fn execute_brute_force(&mut self) {
         for ref_coord in &self.ref_coords {
             let mut min_dist: f32 = 999999.0;
+            let mut min_idx: usize = 0;
             let mut dist: f32;
-            for other_coord in &self.other_coords {
-                dist = ref_coord.distance_to(&other_coord);
+            for (idx, other_coord) in self.other_coords.iter().enumerate() {
+                dist = ref_coord.distance_to(other_coord);
                 //dist = other_coord.distance_to(&ref_coord);
 
                 if dist.lt(&min_dist) {
                     min_dist = dist;
+                    min_idx = idx;
                 };
             }
             let _ = &self.distances.push(min_dist);
+            let _ = &self.nearest.push(min_idx);
+        }
+    }
+    /// Directed Hausdorff distance only. With the k-d tree backend, reference points are
+    /// visited in a scattered order and skipped as soon as any other surface point is found
+    /// within the current maximum, since they cannot raise it (Taha & Hanbury, 2015). `Auto`
+    /// picks the k-d tree here, as the early exit usually makes it the cheapest; the other
+    /// backends compute all distances as in `execute`.
+    pub fn execute_hd(&mut self) -> f32 {
+        self.collect_coords();
+        let n_ref = self.ref_coords.len();
+        if n_ref == 0 {
+            return -1.0;
         }
+        if self.other_coords.is_empty() {
+            return 999999.0;
+        }
+        if !matches!(self.backend, DistanceBackend::KdTree | DistanceBackend::Auto) {
+            self.execute();
+            return self.hd();
+        }
+        let tree = KdTree::new(Self::physical_points(&self.other_coords));
+        let ref_points = Self::physical_points(&self.ref_coords);
+        let stride = if n_ref.is_multiple_of(7919) { 1 } else { 7919 };
+        let mut max_dist: f32 = -1.0;
+        for i in 0..n_ref {
+            let point = &ref_points[(i * stride) % n_ref];
+            if (max_dist >= 0.0) && tree.any_within(point, max_dist) {
+                continue;
+            }
+            max_dist = max_dist.max(tree.nearest(point).unwrap().1);
+        }
+        max_dist
+    }
+    /// Distance from every reference surface point to the other surface.
+    pub fn distances(&self) -> &[f32] {
+        &self.distances
+    }
+    /// Voxel index of every reference surface point, in the order of `distances`.
+    pub fn ref_points(&self) -> Vec<[usize; 3]> {
+        self.ref_coords.iter()
+            .map(|c| [c.z.idx as usize, c.y.idx as usize, c.x.idx as usize])
+            .collect()
+    }
+    /// Vector (mm, zyx) from every reference surface point to its nearest other surface point.
+    /// NaN if the other surface is empty.
+    pub fn displacements(&self) -> Vec<[f32; 3]> {
+        // The distance transform backend does not record nearest points
+        let nearest = if self.nearest.is_empty() & !self.other_coords.is_empty() {
+            let tree = KdTree::new(Self::physical_points(&self.other_coords));
+            Self::physical_points(&self.ref_coords).iter()
+                .map(|p| tree.nearest(p).unwrap().0)
+                .collect()
+        } else {
+            self.nearest.clone()
+        };
+        self.ref_coords.iter().enumerate()
+            .map(|(i, ref_coord)| {
+                if self.other_coords.is_empty() {
+                    return [f32::NAN; 3];
+                }
+                let other_coord = &self.other_coords[nearest[i]];
+                [
+                    other_coord.z.physical_point() - ref_coord.z.physical_point(),
+                    other_coord.y.physical_point() - ref_coord.y.physical_point(),
+                    other_coord.x.physical_point() - ref_coord.x.physical_point(),
+                ]
+            })
+            .collect()
     }
     pub fn hd(&self) -> f32{
         let mut max_dist: f32 = -1.0;
@@ -99,11 +338,15 @@ impl HausdorffMapDirected {
         for dist in &self.distances {
             sum += *dist;
             }
-        &sum / self.distances.len() as f32
+        sum / self.distances.len() as f32
     }
 
+    /// -1 without reference surface points, like `hd`.
     pub fn hd_percentile(&mut self, percentile: &f32) -> f32{
         assert!((percentile.le(&1.0) & percentile.ge(&0.0)));
+        if self.distances.is_empty() {
+            return -1.0;
+        }
 
         let mut sorted_distances = self.distances.clone();
         sorted_distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
@@ -117,7 +360,7 @@ impl HausdorffMapDirected {
     pub fn surface_dc(&self, tolerance: &f32) -> f32 {
         let mut within: u32 = 0;
         for d in &self.distances{
-            if d.le(&tolerance){
+            if d.le(tolerance){
                 within += 1;
             }
         }
@@ -136,23 +379,61 @@ impl HausdorffMapUndirected {
                zyx_spacing: Vec<f32>) -> HausdorffMapUndirected {
         HausdorffMapUndirected {
             ref_to_other_map: HausdorffMapDirected::new(
-                &ref_arr,
-                &other_arr,
+                ref_arr,
+                other_arr,
                 zyx_spacing.clone()
             ),
             other_to_ref_map: HausdorffMapDirected::new(
-                &other_arr,
-                &ref_arr,
+                other_arr,
+                ref_arr,
                 zyx_spacing.clone()
             ),
         }
     }
+    /// Map from precomputed directed distances, see `HausdorffMapDirected::from_distances`.
+    pub fn from_distances(ref_to_other: Vec<f32>, other_to_ref: Vec<f32>) -> HausdorffMapUndirected {
+        HausdorffMapUndirected {
+            ref_to_other_map: HausdorffMapDirected::from_distances(ref_to_other),
+            other_to_ref_map: HausdorffMapDirected::from_distances(other_to_ref),
+        }
+    }
+    /// Discard surface points of both structures that fall outside `roi`.
+    pub fn with_roi(self, roi: &Array<bool, Ix3>) -> HausdorffMapUndirected {
+        HausdorffMapUndirected {
+            ref_to_other_map: self.ref_to_other_map.with_roi(roi),
+            other_to_ref_map: self.other_to_ref_map.with_roi(roi),
+        }
+    }
+    /// Discard surface points of both structures that fall inside `ignore`.
+    pub fn with_ignore(self, ignore: &Array<bool, Ix3>) -> HausdorffMapUndirected {
+        HausdorffMapUndirected {
+            ref_to_other_map: self.ref_to_other_map.with_ignore(ignore),
+            other_to_ref_map: self.other_to_ref_map.with_ignore(ignore),
+        }
+    }
+    /// See `HausdorffMapDirected::with_slices`.
+    pub fn with_slices(self, slices: &[usize], axis: usize) -> HausdorffMapUndirected {
+        HausdorffMapUndirected {
+            ref_to_other_map: self.ref_to_other_map.with_slices(slices, axis),
+            other_to_ref_map: self.other_to_ref_map.with_slices(slices, axis),
+        }
+    }
+    pub fn with_backend(self, backend: DistanceBackend) -> HausdorffMapUndirected {
+        HausdorffMapUndirected {
+            ref_to_other_map: self.ref_to_other_map.with_backend(backend),
+            other_to_ref_map: self.other_to_ref_map.with_backend(backend),
+        }
+    }
     pub fn execute(&mut self) {
         let _ = &self.ref_to_other_map.execute();
         let _ = &self.other_to_ref_map.execute();
     }
+    /// Undirected Hausdorff distance only, see `HausdorffMapDirected::execute_hd`.
+    pub fn execute_hd(&mut self) -> f32 {
+        self.ref_to_other_map.execute_hd().max(self.other_to_ref_map.execute_hd())
+    }
     pub fn avg_hd(&self) -> f32 {
-        (self.ref_to_other_map.hd() + self.other_to_ref_map.hd()) / 2 as f32
+        (self.ref_to_other_map.hd() + self.other_to_ref_map.hd()) / 2_f32
     }
     pub fn hd(&self) -> f32 {
         let ref_hd = self.ref_to_other_map.hd();
@@ -164,11 +445,18 @@ impl HausdorffMapUndirected {
         }
     }
     pub fn assd(&self) -> f32 {
-        (self.ref_to_other_map.asd() + self.other_to_ref_map.asd()) / 2 as f32
+        (self.ref_to_other_map.asd() + self.other_to_ref_map.asd()) / 2_f32
+    }
+    /// Fraction of both surfaces within `tolerance` of the other surface.
+    pub fn surface_dc(&self, tolerance: &f32) -> f32 {
+        let distances = self.ref_to_other_map.distances.iter().chain(self.other_to_ref_map.distances.iter());
+        let n_points = self.ref_to_other_map.distances.len() + self.other_to_ref_map.distances.len();
+        let within = distances.filter(|d| d.le(&tolerance)).count();
+        (within as f64 / n_points as f64) as f32
     }
     pub fn avg_hd_percentile(&mut self, percentile: &f32) -> f32{
         assert!((percentile.le(&1.0) & percentile.ge(&0.0)));
-        (self.ref_to_other_map.hd_percentile(percentile) + self.other_to_ref_map.hd_percentile(percentile)) / 2 as f32
+        (self.ref_to_other_map.hd_percentile(percentile) + self.other_to_ref_map.hd_percentile(percentile)) / 2_f32
     }
     pub fn hd_percentile(&mut self, percentile: &f32) -> f32{
         assert!((percentile.le(&1.0) & percentile.ge(&0.0)));
@@ -183,8 +471,9 @@ impl HausdorffMapUndirected {
 }
 #[cfg(test)]
 mod test_hausdorff_map {
-    use ndarray::{Array, Array3, Ix3};
-    use crate::distance::{HausdorffMapDirected, HausdorffMapUndirected};
+    use ndarray::{s, Array, Array3, Ix3};
+    use crate::distance::{DistanceBackend, HausdorffMapDirected, HausdorffMapUndirected};
+    use crate::utils::reference_slices;
 
     fn generate_src_dst_arrays() -> (Array<bool, Ix3>, Array<bool, Ix3>){
         let src = Array3::<u8>::from(vec![
@@ -207,8 +496,8 @@ mod test_hausdorff_map {
                 [0, 1, 1, 1, 0],
                 [0, 1, 1, 1, 1]]]);
 
-        let src = src.mapv(|x| (x != 0)) as Array<bool, Ix3>;
-        let dst = dst.mapv(|x| (x != 0)) as Array<bool, Ix3>;
+        let src = src.mapv(|x| x != 0) as Array<bool, Ix3>;
+        let dst = dst.mapv(|x| x != 0) as Array<bool, Ix3>;
         (src, dst)
     }
 
@@ -257,4 +546,71 @@ mod test_hausdorff_map {
         assert!(hd_map.avg_hd_percentile(&0.95).eq(&1.5));
         assert!(hd_map.avg_hd_percentile(&0.0).eq(&0.0));
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_roi() {
+        let (src, dst) = generate_src_dst_arrays();
+        let mut roi = Array3::<bool>::from_elem([2, 4, 5], true);
+        for x in 0..5 {
+            roi[[1, 3, x]] = false;
+        }
+
+        let mut hd_map = HausdorffMapDirected::new(&src, &dst, vec![1.0, 1.0, 1.0]).with_roi(&roi);
+        hd_map.execute();
+        assert!(hd_map.other_coords.iter().all(|c| c.y.idx < 3));
+
+        let mut hd_map = HausdorffMapUndirected::new(&src, &dst, vec![1.0, 1.0, 1.0]).with_roi(&roi);
+        hd_map.execute();
+        assert!(hd_map.hd().eq(&1.0));
+
+        let mut hd_map = HausdorffMapDirected::new(&src, &dst, vec![1.0, 1.0, 1.0]).with_ignore(&roi.mapv(|v| !v));
+        hd_map.execute();
+        assert!(hd_map.other_coords.iter().all(|c| c.y.idx < 3));
+
+        let mut hd_map = HausdorffMapDirected::new(&src, &dst, vec![1.0, 1.0, 1.0]).with_slices(&[1], 0);
+        hd_map.execute();
+        assert!(hd_map.ref_coords.iter().chain(hd_map.other_coords.iter()).all(|c| c.z.idx == 1));
+        let mut hd_map = HausdorffMapUndirected::new(&src, &dst, vec![1.0, 1.0, 1.0]).with_roi(&roi).with_slices(&[1], 0);
+        hd_map.execute();
+        assert!(hd_map.ref_to_other_map.other_coords.iter().all(|c| (c.z.idx == 1) & (c.y.idx < 3)));
+    }
+    #[test]
+    fn test_slices_cut_both_masks() {
+        // The prediction continues past the slices the reference was contoured on
+        let mut src = Array3::<bool>::from_elem([10, 9, 9], false);
+        src.slice_mut(s![3..7, 1..8, 1..8]).fill(true);
+        let mut dst = Array3::<bool>::from_elem([10, 9, 9], false);
+        dst.slice_mut(s![0..10, 1..8, 1..8]).fill(true);
+        let slices = reference_slices(&src, 0, &[1.0, 1.0, 1.0], 0.0);
+
+        let mut hd_map = HausdorffMapUndirected::new(&src, &dst, vec![1.0, 1.0, 1.0]).with_slices(&slices, 0);
+        hd_map.execute();
+        assert_eq!(hd_map.hd(), 0.0);
+    }
+    #[test]
+    fn test_backends_agree() {
+        let (src, dst) = generate_src_dst_arrays();
+        let spacing = vec![3.09, 4.1231, 2.343];
+        let mut brute = HausdorffMapDirected::new(&src, &dst, spacing.clone()).with_backend(DistanceBackend::BruteForce);
+        brute.execute();
+        let mut kd = HausdorffMapDirected::new(&src, &dst, spacing.clone()).with_backend(DistanceBackend::KdTree);
+        kd.execute();
+        let mut dt = HausdorffMapDirected::new(&src, &dst, spacing.clone()).with_backend(DistanceBackend::DistanceTransform);
+        dt.execute();
+
+        assert_eq!(kd.distances(), brute.distances());
+        for (a, b) in dt.distances().iter().zip(brute.distances()) {
+            assert!((a - b).abs() < 1e-5);
+        }
+        assert_eq!(dt.displacements(), kd.displacements());
+
+        let mut early = HausdorffMapDirected::new(&src, &dst, spacing.clone());
+        assert_eq!(early.execute_hd(), brute.hd());
+        let mut dt = HausdorffMapDirected::new(&src, &dst, spacing.clone()).with_backend(DistanceBackend::DistanceTransform);
+        assert!((dt.execute_hd() - brute.hd()).abs() < 1e-5);
+        assert_eq!(dt.distances().len(), brute.distances().len());
+        let mut undirected = HausdorffMapUndirected::new(&src, &dst, spacing.clone()).with_backend(DistanceBackend::KdTree);
+        let mut early = HausdorffMapUndirected::new(&src, &dst, spacing);
+        undirected.execute();
+        assert_eq!(early.execute_hd(), undirected.hd());
+    }
+}
@@ -0,0 +1,185 @@
+use ndarray::{s, Array, Array2, Ix3, Zip};
+use crate::distance::HausdorffMapUndirected;
+use crate::overlap::ConfusionMatrix;
+use crate::utils::{distance_transform, generate_edge, joint_crop};
+
+/// Pairwise agreement between N raters. Each mask's edge points and the distance
+/// transform of its edge map are computed once and shared by all pairs.
+/// Entry [i, j] compares rater i (as reference) with rater j. Distance metrics are NaN
+/// for pairs where either mask is empty.
+pub struct InterObserver {
+    masks: Vec<Array<bool, Ix3>>,
+    zyx_spacing: Vec<f32>,
+    cms: Vec<Vec<ConfusionMatrix>>,
+    distances: Vec<Vec<Vec<f32>>>,
+}
+
+impl InterObserver {
+    pub fn new(masks: &[Array<bool, Ix3>], zyx_spacing: Vec<f32>) -> InterObserver {
+        InterObserver {
+            masks: masks.to_vec(),
+            zyx_spacing,
+            cms: Vec::new(),
+            distances: Vec::new(),
+        }
+    }
+    /// Works on the joint bounding box of all masks, which holds every surface point;
+    /// voxels outside it are true negatives for every pair.
+    pub fn execute(&mut self) {
+        let n = self.masks.len();
+        if n == 0 {
+            return;
+        }
+        let all_masks: Vec<&Array<bool, Ix3>> = self.masks.iter().collect();
+        let [zs, ys, xs] = joint_crop(&all_masks, 0).unwrap_or([0..0, 0..0, 0..0]);
+        let crop = s![zs, ys, xs];
+        let masks: Vec<Array<bool, Ix3>> = self.masks.iter().map(|mask| mask.slice(crop).to_owned()).collect();
+        let n_outside = (self.masks[0].len() - masks[0].len()) as u32;
+
+        let mut edge_points = Vec::<Vec<[usize; 3]>>::with_capacity(n);
+        let mut distance_maps = Vec::<Array<f32, Ix3>>::with_capacity(n);
+        for mask in &masks {
+            let edge = generate_edge(mask, false);
+            edge_points.push(edge.indexed_iter().filter(|(_, v)| **v).map(|((z, y, x), _)| [z, y, x]).collect());
+            distance_maps.push(distance_transform(&edge, &self.zyx_spacing));
+        }
+
+        for (ref_mask, ref_points) in masks.iter().zip(edge_points.iter()) {
+            let mut cm_row = Vec::with_capacity(n);
+            let mut distance_row = Vec::with_capacity(n);
+            for (other_mask, other_distance_map) in masks.iter().zip(distance_maps.iter()) {
+                let (mut tpos, mut tneg, mut fpos, mut fneg) = (0_u32, n_outside, 0_u32, 0_u32);
+                Zip::from(ref_mask).and(other_mask).for_each(|r, o| match (*r, *o) {
+                    (true, true) => tpos += 1,
+                    (false, false) => tneg += 1,
+                    (false, true) => fpos += 1,
+                    (true, false) => fneg += 1,
+                });
+                cm_row.push(ConfusionMatrix::from_counts(tpos, tneg, fpos, fneg));
+                distance_row.push(ref_points.iter().map(|p| other_distance_map[*p]).collect());
+            }
+            self.cms.push(cm_row);
+            self.distances.push(distance_row);
+        }
+    }
+    fn has_surfaces(&self, i: usize, j: usize) -> bool {
+        !self.distances[i][j].is_empty() & !self.distances[j][i].is_empty()
+    }
+    fn undirected_matrix<F: Fn(&mut HausdorffMapUndirected) -> f32>(&self, metric: F) -> Array2<f32> {
+        let n = self.masks.len();
+        Array2::from_shape_fn((n, n), |(i, j)| {
+            if !self.has_surfaces(i, j) {
+                return f32::NAN;
+            }
+            let mut hd_map = HausdorffMapUndirected::from_distances(
+                self.distances[i][j].clone(),
+                self.distances[j][i].clone(),
+            );
+            metric(&mut hd_map)
+        })
+    }
+    pub fn n_raters(&self) -> usize {
+        self.masks.len()
+    }
+    pub fn dc(&self) -> Array2<f32> {
+        let n = self.masks.len();
+        Array2::from_shape_fn((n, n), |(i, j)| self.cms[i][j].dc())
+    }
+    pub fn jc(&self) -> Array2<f32> {
+        let n = self.masks.len();
+        Array2::from_shape_fn((n, n), |(i, j)| self.cms[i][j].jc())
+    }
+    pub fn hd(&self) -> Array2<f32> {
+        self.undirected_matrix(|hd_map| hd_map.hd())
+    }
+    pub fn hd_percentile(&self, percentile: &f32) -> Array2<f32> {
+        self.undirected_matrix(|hd_map| hd_map.hd_percentile(percentile))
+    }
+    pub fn assd(&self) -> Array2<f32> {
+        self.undirected_matrix(|hd_map| hd_map.assd())
+    }
+    /// Surface Dice at `tolerance`, symmetric like `HausdorffMapUndirected::surface_dc`.
+    pub fn surface_dc(&self, tolerance: &f32) -> Array2<f32> {
+        self.undirected_matrix(|hd_map| hd_map.surface_dc(tolerance))
+    }
+}
+
+#[cfg(test)]
+mod test_interobserver {
+    use ndarray::{s, Array, Array3, Ix3};
+    use crate::distance::HausdorffMapUndirected;
+    use crate::interobserver::InterObserver;
+    use crate::overlap::ConfusionMatrix;
+
+    fn generate_rater_arrays() -> Vec<Array<bool, Ix3>> {
+        let mut masks = Vec::new();
+        for shift in 0..3 {
+            let mut mask = Array3::<bool>::from_elem([4, 8, 8], false);
+            for z in 1..3 {
+                for y in 2..5 {
+                    for x in (1 + shift)..(5 + shift) {
+                        mask[[z, y, x]] = true;
+                    }
+                }
+            }
+            masks.push(mask);
+        }
+        masks
+    }
+
+    #[test]
+    fn test_interobserver_matches_pairwise() {
+        let masks = generate_rater_arrays();
+        let spacing = vec![2.0, 1.0, 0.5];
+        let mut inter = InterObserver::new(&masks, spacing.clone());
+        inter.execute();
+
+        let dc = inter.dc();
+        let hd = inter.hd();
+        let hd95 = inter.hd_percentile(&0.95);
+        assert_eq!(inter.n_raters(), 3);
+        for i in 0..3 {
+            assert_eq!(dc[[i, i]], 1.0);
+            assert_eq!(hd[[i, i]], 0.0);
+            for j in 0..3 {
+                let mut cm = ConfusionMatrix::new(&masks[i], &masks[j]);
+                cm.execute();
+                assert_eq!(dc[[i, j]], cm.dc());
+
+                let mut hd_map = HausdorffMapUndirected::new(&masks[i], &masks[j], spacing.clone());
+                hd_map.execute();
+                assert!((hd[[i, j]] - hd_map.hd()).abs() < 1e-6);
+                assert!((hd95[[i, j]] - hd_map.hd_percentile(&0.95)).abs() < 1e-6);
+            }
+        }
+        assert_eq!(inter.surface_dc(&0.5)[[0, 1]], inter.surface_dc(&0.5)[[1, 0]]);
+    }
+    #[test]
+    fn test_interobserver_empty_mask() {
+        let mut masks = generate_rater_arrays();
+        masks.push(Array3::<bool>::from_elem([4, 8, 8], false));
+        let mut inter = InterObserver::new(&masks, vec![1.0, 1.0, 1.0]);
+        inter.execute();
+
+        assert!(inter.hd()[[0, 3]].is_nan());
+        assert!(inter.surface_dc(&1.0)[[3, 0]].is_nan());
+        assert_eq!(inter.dc()[[0, 3]], 0.0);
+    }
+    #[test]
+    fn test_interobserver_surface_dc_raters_of_different_size() {
+        let mut small = Array3::<bool>::from_elem([6, 10, 10], false);
+        small.slice_mut(s![2..4, 3..6, 3..6]).fill(true);
+        let mut large = Array3::<bool>::from_elem([6, 10, 10], false);
+        large.slice_mut(s![1..5, 1..9, 1..9]).fill(true);
+        let spacing = vec![1.0, 1.0, 1.0];
+        let mut inter = InterObserver::new(&[small.clone(), large.clone()], spacing.clone());
+        inter.execute();
+
+        let surface_dc = inter.surface_dc(&1.0);
+        let mut hd_map = HausdorffMapUndirected::new(&small, &large, spacing);
+        hd_map.execute();
+        assert_eq!(surface_dc[[0, 1]], surface_dc[[1, 0]]);
+        assert!((surface_dc[[0, 1]] - hd_map.surface_dc(&1.0)).abs() < 1e-6);
+        assert!(surface_dc[[0, 1]] < 1.0);
+    }
+}
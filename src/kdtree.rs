@@ -0,0 +1,122 @@
+/// Balanced k-d tree over 3D points (mm) for nearest-neighbour queries. The tree is stored
+/// implicitly: the median of every index range is the node, the halves are its subtrees.
+pub struct KdTree {
+    points: Vec<[f32; 3]>,
+    order: Vec<usize>,
+    axes: Vec<u8>,
+}
+
+fn squared_distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    (b[0] - a[0]).powf(2.0) + (b[1] - a[1]).powf(2.0) + (b[2] - a[2]).powf(2.0)
+}
+
+impl KdTree {
+    pub fn new(points: Vec<[f32; 3]>) -> KdTree {
+        let mut tree = KdTree {
+            order: (0..points.len()).collect(),
+            axes: vec![0; points.len()],
+            points,
+        };
+        tree.build(0, tree.points.len());
+        tree
+    }
+    fn build(&mut self, lo: usize, hi: usize) {
+        if hi <= lo {
+            return;
+        }
+        // Split along the axis with the largest spread
+        let mut spread = [0.0_f32; 3];
+        for (axis, s) in spread.iter_mut().enumerate() {
+            let values = self.order[lo..hi].iter().map(|i| self.points[*i][axis]);
+            let (min, max) = values.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| (min.min(v), max.max(v)));
+            *s = max - min;
+        }
+        let axis = (0..3).max_by(|a, b| spread[*a].partial_cmp(&spread[*b]).unwrap()).unwrap();
+        let mid = (lo + hi) / 2;
+        let points = &self.points;
+        self.order[lo..hi].select_nth_unstable_by(mid - lo, |a, b| points[*a][axis].partial_cmp(&points[*b][axis]).unwrap());
+        self.axes[mid] = axis as u8;
+        self.build(lo, mid);
+        self.build(mid + 1, hi);
+    }
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+    /// Index and distance of the point closest to `query`; ties go to the lowest index.
+    pub fn nearest(&self, query: &[f32; 3]) -> Option<(usize, f32)> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut best = (usize::MAX, f32::INFINITY);
+        self.search(query, 0, self.points.len(), &mut best, None);
+        Some((best.0, best.1.sqrt()))
+    }
+    /// Whether any point lies within `radius` of `query`, stopping at the first one found.
+    pub fn any_within(&self, query: &[f32; 3], radius: f32) -> bool {
+        let mut best = (usize::MAX, f32::INFINITY);
+        self.search(query, 0, self.points.len(), &mut best, Some(radius))
+    }
+    /// Descends into the near half first and visits the far half only if the splitting
+    /// plane is closer than the best candidate. Returns true once a point within
+    /// `stop_radius` is found; the radius is compared after the square root, like `nearest`.
+    fn search(&self, query: &[f32; 3], lo: usize, hi: usize, best: &mut (usize, f32), stop_radius: Option<f32>) -> bool {
+        if hi <= lo {
+            return false;
+        }
+        let mid = (lo + hi) / 2;
+        let idx = self.order[mid];
+        let d = squared_distance(query, &self.points[idx]);
+        if (d < best.1) | ((d == best.1) & (idx < best.0)) {
+            *best = (idx, d);
+        }
+        if let Some(radius) = stop_radius {
+            if d.sqrt() <= radius {
+                return true;
+            }
+        }
+        let axis = self.axes[mid] as usize;
+        let delta = query[axis] - self.points[idx][axis];
+        let (near, far) = if delta < 0.0 { ((lo, mid), (mid + 1, hi)) } else { ((mid + 1, hi), (lo, mid)) };
+        if self.search(query, near.0, near.1, best, stop_radius) {
+            return true;
+        }
+        if delta * delta <= best.1 {
+            return self.search(query, far.0, far.1, best, stop_radius);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test_kdtree {
+    use crate::kdtree::KdTree;
+
+    #[test]
+    fn test_kdtree_matches_brute_force() {
+        // Deterministic pseudo-random points
+        let mut state = 12345_u32;
+        let mut next = || {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as f32 / 65536.0 * 50.0
+        };
+        let points: Vec<[f32; 3]> = (0..500).map(|_| [next(), next(), next()]).collect();
+        let queries: Vec<[f32; 3]> = (0..100).map(|_| [next(), next(), next()]).collect();
+        let tree = KdTree::new(points.clone());
+        assert_eq!(tree.len(), 500);
+
+        for q in &queries {
+            let (idx, dist) = tree.nearest(q).unwrap();
+            let brute = points.iter()
+                .map(|p| ((p[0] - q[0]).powf(2.0) + (p[1] - q[1]).powf(2.0) + (p[2] - q[2]).powf(2.0)).sqrt())
+                .fold(f32::INFINITY, f32::min);
+            assert_eq!(dist, brute);
+            assert_eq!(tree.points[idx], points[idx]);
+            assert!(tree.any_within(q, brute));
+            assert!(!tree.any_within(q, brute * 0.99));
+        }
+        assert!(KdTree::new(Vec::new()).nearest(&[0.0, 0.0, 0.0]).is_none());
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use ndarray::{s, Array, Ix3};
+use crate::distance::HausdorffMapUndirected;
+use crate::overlap::ConfusionMatrix;
+use crate::utils::label_components;
+
+/// How reference and predicted lesions are paired.
+pub enum LesionMatching {
+    /// Pair lesions that share voxels, largest overlap first.
+    Overlap,
+    /// Pair lesions whose centroids are within the given distance (mm), closest first.
+    CentroidDistance(f32),
+}
+
+/// Lesion-wise (instance) detection metrics. Both masks are split into connected
+/// components, which are matched one-to-one. Per-lesion Dice and HD are reported for
+/// every reference lesion; missed lesions get Dice 0 and HD NaN.
+pub struct LesionDetection {
+    ref_arr: Array<bool, Ix3>,
+    other_arr: Array<bool, Ix3>,
+    zyx_spacing: Vec<f32>,
+    connectivity: u8,
+    matching: LesionMatching,
+    n_ref: u32,
+    n_other: u32,
+    matches: Vec<(u32, u32)>,
+    lesion_dcs: Vec<f32>,
+    lesion_hds: Vec<f32>,
+}
+
+fn centroids(labels: &Array<u32, Ix3>, n_labels: u32, zyx_spacing: &[f32]) -> Vec<[f32; 3]> {
+    let mut sums = vec![[0.0_f64; 3]; n_labels as usize];
+    let mut counts = vec![0_u64; n_labels as usize];
+    for ((z, y, x), label) in labels.indexed_iter() {
+        if *label == 0 {
+            continue;
+        }
+        let i = (*label - 1) as usize;
+        sums[i][0] += z as f64;
+        sums[i][1] += y as f64;
+        sums[i][2] += x as f64;
+        counts[i] += 1;
+    }
+    sums.iter().zip(counts.iter())
+        .map(|(sum, count)| [
+            (sum[0] / *count as f64) as f32 * zyx_spacing[0],
+            (sum[1] / *count as f64) as f32 * zyx_spacing[1],
+            (sum[2] / *count as f64) as f32 * zyx_spacing[2],
+        ])
+        .collect()
+}
+
+/// Bounding box ([first, last] index along z, y, x) of every label, in a single pass.
+fn label_bounding_boxes(labels: &Array<u32, Ix3>, n_labels: u32) -> Vec<[[usize; 2]; 3]> {
+    let mut bboxes = vec![[[usize::MAX, 0]; 3]; n_labels as usize];
+    for ((z, y, x), label) in labels.indexed_iter() {
+        if *label == 0 {
+            continue;
+        }
+        let bbox = &mut bboxes[(*label - 1) as usize];
+        for (bounds, idx) in bbox.iter_mut().zip([z, y, x]) {
+            bounds[0] = bounds[0].min(idx);
+            bounds[1] = bounds[1].max(idx);
+        }
+    }
+    bboxes
+}
+
+impl LesionDetection {
+    pub fn new(ref_arr: &Array<bool, Ix3>,
+               other_arr: &Array<bool, Ix3>,
+               zyx_spacing: Vec<f32>,
+               connectivity: u8,
+               matching: LesionMatching) -> LesionDetection {
+        LesionDetection {
+            ref_arr: ref_arr.clone(),
+            other_arr: other_arr.clone(),
+            zyx_spacing,
+            connectivity,
+            matching,
+            n_ref: 0,
+            n_other: 0,
+            matches: Vec::new(),
+            lesion_dcs: Vec::new(),
+            lesion_hds: Vec::new(),
+        }
+    }
+    pub fn execute(&mut self) {
+        let (ref_labels, n_ref) = label_components(&self.ref_arr, self.connectivity);
+        let (other_labels, n_other) = label_components(&self.other_arr, self.connectivity);
+        self.n_ref = n_ref;
+        self.n_other = n_other;
+
+        // Candidate pairs, sorted so that the best match comes first
+        let mut candidates: Vec<(u32, u32, f32)> = Vec::new();
+        match self.matching {
+            LesionMatching::Overlap => {
+                let mut overlaps = HashMap::<(u32, u32), u32>::new();
+                for (ref_label, other_label) in ref_labels.iter().zip(other_labels.iter()) {
+                    if (*ref_label != 0) & (*other_label != 0) {
+                        *overlaps.entry((*ref_label, *other_label)).or_insert(0) += 1;
+                    }
+                }
+                candidates.extend(overlaps.iter().map(|((r, o), count)| (*r, *o, -(*count as f32))));
+            }
+            LesionMatching::CentroidDistance(max_distance) => {
+                let ref_centroids = centroids(&ref_labels, n_ref, &self.zyx_spacing);
+                let other_centroids = centroids(&other_labels, n_other, &self.zyx_spacing);
+                for (r, rc) in ref_centroids.iter().enumerate() {
+                    for (o, oc) in other_centroids.iter().enumerate() {
+                        let dist = ((rc[0] - oc[0]).powi(2) + (rc[1] - oc[1]).powi(2) + (rc[2] - oc[2]).powi(2)).sqrt();
+                        if dist <= max_distance {
+                            candidates.push((r as u32 + 1, o as u32 + 1, dist));
+                        }
+                    }
+                }
+            }
+        }
+        candidates.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap().then(a.0.cmp(&b.0)).then(a.1.cmp(&b.1)));
+
+        let mut ref_matched = vec![0_u32; n_ref as usize];
+        let mut other_matched = vec![false; n_other as usize];
+        for (r, o, _) in candidates {
+            if (ref_matched[(r - 1) as usize] == 0) & !other_matched[(o - 1) as usize] {
+                ref_matched[(r - 1) as usize] = o;
+                other_matched[(o - 1) as usize] = true;
+                self.matches.push((r, o));
+            }
+        }
+
+        let ref_bboxes = label_bounding_boxes(&ref_labels, n_ref);
+        let other_bboxes = label_bounding_boxes(&other_labels, n_other);
+        for r in 1..=n_ref {
+            let o = ref_matched[(r - 1) as usize];
+            if o == 0 {
+                self.lesion_dcs.push(0.0);
+                self.lesion_hds.push(f32::NAN);
+                continue;
+            }
+            let (ref_bbox, other_bbox) = (ref_bboxes[(r - 1) as usize], other_bboxes[(o - 1) as usize]);
+            let bbox = [0, 1, 2].map(|i| [ref_bbox[i][0].min(other_bbox[i][0]), ref_bbox[i][1].max(other_bbox[i][1])]);
+            let crop = s![bbox[0][0]..bbox[0][1] + 1, bbox[1][0]..bbox[1][1] + 1, bbox[2][0]..bbox[2][1] + 1];
+            let ref_lesion = ref_labels.slice(crop).mapv(|l| l == r);
+            let other_lesion = other_labels.slice(crop).mapv(|l| l == o);
+
+            let mut cm = ConfusionMatrix::new(&ref_lesion, &other_lesion);
+            cm.execute();
+            self.lesion_dcs.push(cm.dc());
+
+            let mut hd_map = HausdorffMapUndirected::new(&ref_lesion, &other_lesion, self.zyx_spacing.clone());
+            hd_map.execute();
+            self.lesion_hds.push(hd_map.hd());
+        }
+    }
+    pub fn n_ref(&self) -> u32 {
+        self.n_ref
+    }
+    pub fn n_other(&self) -> u32 {
+        self.n_other
+    }
+    /// Matched (reference label, predicted label) pairs, labels as assigned by `label_components`.
+    pub fn matches(&self) -> &[(u32, u32)] {
+        &self.matches
+    }
+    pub fn tpos(&self) -> u32 {
+        self.matches.len() as u32
+    }
+    pub fn fpos(&self) -> u32 {
+        self.n_other - self.tpos()
+    }
+    pub fn fneg(&self) -> u32 {
+        self.n_ref - self.tpos()
+    }
+    pub fn sensitivity(&self) -> f32 {
+        self.tpos() as f32 / self.n_ref as f32
+    }
+    pub fn fp_per_case(&self) -> f32 {
+        self.fpos() as f32
+    }
+    pub fn f1(&self) -> f32 {
+        2.0 * self.tpos() as f32 / (2 * self.tpos() + self.fpos() + self.fneg()) as f32
+    }
+    pub fn lesion_dc(&self) -> &[f32] {
+        &self.lesion_dcs
+    }
+    pub fn lesion_hd(&self) -> &[f32] {
+        &self.lesion_hds
+    }
+}
+
+#[cfg(test)]
+mod test_lesion {
+    use ndarray::{Array, Array3, Ix3};
+    use crate::lesion::{LesionDetection, LesionMatching};
+
+    fn generate_src_dst_arrays() -> (Array<bool, Ix3>, Array<bool, Ix3>) {
+        let mut src = Array3::<bool>::from_elem([3, 10, 10], false);
+        let mut dst = Array3::<bool>::from_elem([3, 10, 10], false);
+        // Lesion 1: detected exactly
+        for y in 1..3 {
+            for x in 1..3 {
+                src[[1, y, x]] = true;
+                dst[[1, y, x]] = true;
+            }
+        }
+        // Lesion 2: detected, shifted by one voxel
+        for y in 6..8 {
+            for x in 1..3 {
+                src[[1, y, x]] = true;
+                dst[[1, y, x + 1]] = true;
+            }
+        }
+        // Lesion 3: missed
+        src[[1, 8, 8]] = true;
+        // False positive
+        dst[[1, 2, 7]] = true;
+        (src, dst)
+    }
+
+    #[test]
+    fn test_lesion_overlap() {
+        let (src, dst) = generate_src_dst_arrays();
+        let mut detection = LesionDetection::new(&src, &dst, vec![1.0, 1.0, 1.0], 26, LesionMatching::Overlap);
+        detection.execute();
+
+        assert_eq!(detection.n_ref(), 3);
+        assert_eq!(detection.n_other(), 3);
+        assert_eq!((detection.tpos(), detection.fpos(), detection.fneg()), (2, 1, 1));
+        assert_eq!(detection.sensitivity(), 2.0 / 3.0);
+        assert_eq!(detection.fp_per_case(), 1.0);
+        assert_eq!(detection.f1(), 4.0 / 6.0);
+        assert_eq!(detection.lesion_dc(), &[1.0, 0.5, 0.0]);
+        assert_eq!(&detection.lesion_hd()[..2], &[0.0, 1.0]);
+        assert!(detection.lesion_hd()[2].is_nan());
+    }
+    #[test]
+    fn test_lesion_centroid_distance() {
+        let (src, dst) = generate_src_dst_arrays();
+        let mut detection = LesionDetection::new(&src, &dst, vec![1.0, 1.0, 1.0], 26, LesionMatching::CentroidDistance(0.5));
+        detection.execute();
+        assert_eq!(detection.tpos(), 1);
+
+        let mut detection = LesionDetection::new(&src, &dst, vec![1.0, 1.0, 1.0], 26, LesionMatching::CentroidDistance(1.5));
+        detection.execute();
+        assert_eq!(detection.tpos(), 2);
+    }
+}
@@ -1,16 +1,131 @@
 pub mod utils;
+pub mod bitmask;
 pub mod overlap;
 pub mod distance;
 pub mod apl;
+pub mod profile;
+pub mod lesion;
+pub mod panoptic;
+pub mod soft;
+pub mod calibration;
+pub mod consensus;
+pub mod interobserver;
+pub mod volume;
+pub mod shape;
+pub mod displacement;
+pub mod directional;
+pub mod centerline;
+pub mod topology;
+pub mod morphology;
+pub mod boundary;
+pub mod mesh;
+pub mod kdtree;
+pub mod rle;
+pub mod reference;
 use ndarray::prelude::*;
-use numpy::{PyArrayDyn, ToPyArray, PyReadonlyArrayDyn, PyReadonlyArray3, PyArray};
-use Vec;
+use numpy::{PyArrayDyn, ToPyArray, PyReadonlyArrayDyn, PyReadonlyArray2, PyReadonlyArray3, PyArray, PyArray1};
 use pyo3::{
-    pymodule,
-    types::{PyModule},
+    exceptions::PyValueError,
+    pyclass, pymethods, pymodule,
+    types::{PyDict, PyModule},
     PyResult, Python
 };
 
+/// Pair of masks handed back to Python.
+type MaskPair<'py> = (&'py PyArray<bool, Ix3>, &'py PyArray<bool, Ix3>);
+
+/// Error unless `axis` names one of the three volume axes.
+fn check_axis(axis: usize) -> PyResult<()> {
+    if axis > 2 {
+        return Err(PyValueError::new_err(format!("axis must be 0, 1 or 2, got {}", axis)));
+    }
+    Ok(())
+}
+
+/// Per-slice RLE order: "C" (row by row) or "F" (column by column, as in COCO).
+fn parse_slice_order(order: &str) -> PyResult<crate::rle::SliceOrder> {
+    match order {
+        "C" => Ok(crate::rle::SliceOrder::C),
+        "F" => Ok(crate::rle::SliceOrder::Fortran),
+        _ => Err(PyValueError::new_err(format!("order must be 'C' or 'F', got '{}'", order))),
+    }
+}
+
+/// Error unless the reference and other masks have the same shape.
+fn check_same_shape(ref_shape: &[usize], other_shape: &[usize]) -> PyResult<()> {
+    if ref_shape != other_shape {
+        return Err(PyValueError::new_err(format!("masks must have the same shape, got {:?} and {:?}", ref_shape, other_shape)));
+    }
+    Ok(())
+}
+
+/// Error unless there is at least one mask and all masks have the same shape.
+fn check_masks(masks: &[PyReadonlyArray3<bool>]) -> PyResult<()> {
+    let Some(first) = masks.first() else {
+        return Err(PyValueError::new_err("need at least one mask"));
+    };
+    for mask in masks {
+        check_same_shape(first.shape(), mask.shape())?;
+    }
+    Ok(())
+}
+
+/// Error unless the optional `roi` and `ignore` masks have the shape of the evaluated masks.
+fn check_roi(shape: &[usize], roi: &Option<PyReadonlyArray3<bool>>, ignore: &Option<PyReadonlyArray3<bool>>) -> PyResult<()> {
+    for (name, mask) in [("roi", roi), ("ignore", ignore)] {
+        if let Some(mask) = mask {
+            if mask.shape() != shape {
+                return Err(PyValueError::new_err(format!("{} must have shape {:?}, got {:?}", name, shape, mask.shape())));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reference mask prepared once and evaluated against many predictions, created with
+/// `reference_surface`.
+#[pyclass]
+struct ReferenceSurface {
+    inner: crate::reference::ReferenceSurface,
+}
+
+#[pymethods]
+impl ReferenceSurface {
+    #[getter]
+    fn volume(&self) -> f32 {
+        self.inner.volume()
+    }
+    #[getter]
+    fn bounding_box(&self) -> Option<[[usize; 2]; 3]> {
+        self.inner.bounding_box()
+    }
+    fn distance_map<'py>(&self, _py: Python<'py>) -> &'py PyArray<f32, Ix3> {
+        self.inner.distance_map().to_pyarray(_py)
+    }
+    #[pyo3(signature = (other_arr, percentile=0.95, tolerance=1.0))]
+    fn evaluate<'py>(&self,
+                     _py: Python<'py>,
+                     other_arr: PyReadonlyArray3<'py, bool>,
+                     percentile: f32,
+                     tolerance: f32,
+    ) -> PyResult<&'py PyDict> {
+        let other_arr = other_arr.to_owned_array();
+        let error = || PyValueError::new_err("other_arr must have the shape of the reference");
+        let cm = self.inner.confusion_matrix(&other_arr).ok_or_else(error)?;
+        let mut hd_map = self.inner.hausdorff_map(&other_arr).ok_or_else(error)?;
+        let dict = PyDict::new(_py);
+        dict.set_item("dc", cm.dc())?;
+        dict.set_item("jc", cm.jc())?;
+        dict.set_item("hd", hd_map.hd())?;
+        dict.set_item("hd_percentile", hd_map.hd_percentile(&percentile))?;
+        dict.set_item("assd", hd_map.assd())?;
+        dict.set_item("surface_dc", hd_map.surface_dc(&tolerance))?;
+        let voxel_ml = crate::volume::voxel_volume(self.inner.zyx_spacing()) as f64 / 1000.0;
+        dict.set_item("volume_difference", ((cm.other_count() as f64 - cm.ref_count() as f64) * voxel_ml) as f32)?;
+        Ok(dict)
+    }
+}
+
 /// A Python module implemented in Rust.
 #[pymodule]
 fn volumetrics<'py>(_py: Python<'py>, m: &'py PyModule) -> PyResult<()> {
@@ -28,91 +143,904 @@ fn volumetrics<'py>(_py: Python<'py>, m: &'py PyModule) -> PyResult<()> {
         edge_arr.to_pyarray(_py)
     }
     #[pyfn(m)]
-    #[pyo3(name = "surface_dice")]
+    #[pyo3(name = "surface_dice", signature = (ref_arr, other_arr, zyx_spacing, tolerances, roi=None, ignore=None))]
     fn surface_dice<'py>(_py: Python<'py>,
                          ref_arr: PyReadonlyArray3<'py, bool>,
                          other_arr: PyReadonlyArray3<'py, bool>,
                          zyx_spacing: Vec<f32>,
-                         tolerances: Vec<f32>) -> Vec<f32> {
-
+                         tolerances: Vec<f32>,
+                         roi: Option<PyReadonlyArray3<'py, bool>>,
+                         ignore: Option<PyReadonlyArray3<'py, bool>>) -> PyResult<Vec<f32>> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        check_roi(ref_arr.shape(), &roi, &ignore)?;
         let ref_arr = ref_arr.to_owned_array();
         let other_arr = other_arr.to_owned_array();
 
         let mut hd_map = crate::distance::HausdorffMapDirected::new(&ref_arr, &other_arr, zyx_spacing);
+        if let Some(roi) = roi {
+            hd_map = hd_map.with_roi(&roi.to_owned_array());
+        }
+        if let Some(ignore) = ignore {
+            hd_map = hd_map.with_ignore(&ignore.to_owned_array());
+        }
         hd_map.execute();
         let mut sds = Vec::<f32>::new();
         for t in tolerances{
             sds.push(hd_map.surface_dc(&t))
         }
-        sds
+        Ok(sds)
     }
     #[pyfn(m)]
-    #[pyo3(name = "hd")]
+    #[pyo3(name = "hd", signature = (ref_arr, other_arr, zyx_spacing, undirected, roi=None, ignore=None))]
     fn hd<'py>(_py: Python<'py>,
                ref_arr: PyReadonlyArray3<'py, bool>,
                other_arr: PyReadonlyArray3<'py, bool>,
                zyx_spacing: Vec<f32>,
                undirected: bool,
-    ) -> f32 {
+               roi: Option<PyReadonlyArray3<'py, bool>>,
+               ignore: Option<PyReadonlyArray3<'py, bool>>,
+    ) -> PyResult<f32> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        check_roi(ref_arr.shape(), &roi, &ignore)?;
         let ref_arr = ref_arr.to_owned_array();
         let other_arr = other_arr.to_owned_array();
+        let roi = roi.map(|roi| roi.to_owned_array());
+        let ignore = ignore.map(|ignore| ignore.to_owned_array());
 
         if undirected {
             let mut hd_map = crate::distance::HausdorffMapUndirected::new(&ref_arr, &other_arr, zyx_spacing);
-            hd_map.execute();
-            hd_map.hd()
+            if let Some(roi) = &roi {
+                hd_map = hd_map.with_roi(roi);
+            }
+            if let Some(ignore) = &ignore {
+                hd_map = hd_map.with_ignore(ignore);
+            }
+            Ok(hd_map.execute_hd())
         } else {
             let mut hd_map = crate::distance::HausdorffMapDirected::new(&ref_arr, &other_arr, zyx_spacing);
-            hd_map.execute();
-            hd_map.hd()
+            if let Some(roi) = &roi {
+                hd_map = hd_map.with_roi(roi);
+            }
+            if let Some(ignore) = &ignore {
+                hd_map = hd_map.with_ignore(ignore);
+            }
+            Ok(hd_map.execute_hd())
         }
     }
     #[pyfn(m)]
-    #[pyo3(name = "hd_percentile")]
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(name = "hd_percentile", signature = (ref_arr, other_arr, zyx_spacing, percentile, undirected, roi=None, ignore=None))]
     fn hd_percentile<'py>(_py: Python<'py>,
                           ref_arr: PyReadonlyArray3<'py, bool>,
                           other_arr: PyReadonlyArray3<'py, bool>,
                           zyx_spacing: Vec<f32>,
                           percentile: f32,
                           undirected: bool,
-    ) -> f32 {
+                          roi: Option<PyReadonlyArray3<'py, bool>>,
+                          ignore: Option<PyReadonlyArray3<'py, bool>>,
+    ) -> PyResult<f32> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        check_roi(ref_arr.shape(), &roi, &ignore)?;
         let ref_arr = ref_arr.to_owned_array();
         let other_arr = other_arr.to_owned_array();
+        let roi = roi.map(|roi| roi.to_owned_array());
+        let ignore = ignore.map(|ignore| ignore.to_owned_array());
 
         if undirected {
             let mut hd_map = crate::distance::HausdorffMapUndirected::new(&ref_arr, &other_arr, zyx_spacing);
+            if let Some(roi) = &roi {
+                hd_map = hd_map.with_roi(roi);
+            }
+            if let Some(ignore) = &ignore {
+                hd_map = hd_map.with_ignore(ignore);
+            }
             hd_map.execute();
-            hd_map.hd_percentile(&percentile)
+            Ok(hd_map.hd_percentile(&percentile))
         } else {
             let mut hd_map = crate::distance::HausdorffMapDirected::new(&ref_arr, &other_arr, zyx_spacing);
+            if let Some(roi) = &roi {
+                hd_map = hd_map.with_roi(roi);
+            }
+            if let Some(ignore) = &ignore {
+                hd_map = hd_map.with_ignore(ignore);
+            }
             hd_map.execute();
-            hd_map.hd_percentile(&percentile)
+            Ok(hd_map.hd_percentile(&percentile))
         }
     }
     #[pyfn(m)]
-    #[pyo3(name = "dc")]
+    #[pyo3(name = "dc", signature = (ref_arr, other_arr, roi=None, ignore=None))]
     fn dc<'py>(_py: Python<'py>,
                ref_arr: PyReadonlyArray3<'py, bool>,
                other_arr: PyReadonlyArray3<'py, bool>,
-    ) -> f32 {
-        let ref_arr = ref_arr.to_owned_array();
-        let other_arr = other_arr.to_owned_array();
-        let mut cm = crate::overlap::ConfusionMatrix::new(&ref_arr, &other_arr);
+               roi: Option<PyReadonlyArray3<'py, bool>>,
+               ignore: Option<PyReadonlyArray3<'py, bool>>,
+    ) -> PyResult<f32> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        check_roi(ref_arr.shape(), &roi, &ignore)?;
+        let ref_mask = crate::bitmask::BitMask::from_view(ref_arr.as_array());
+        let other_mask = crate::bitmask::BitMask::from_view(other_arr.as_array());
+        let mut cm = crate::overlap::ConfusionMatrix::from_masks(ref_mask, other_mask);
+        if let Some(roi) = roi {
+            cm = cm.with_roi_mask(crate::bitmask::BitMask::from_view(roi.as_array()));
+        }
+        if let Some(ignore) = ignore {
+            cm = cm.with_ignore(&ignore.to_owned_array());
+        }
         cm.execute();
-        cm.dc()
+        Ok(cm.dc())
     }
     #[pyfn(m)]
-    #[pyo3(name = "jc")]
+    #[pyo3(name = "jc", signature = (ref_arr, other_arr, roi=None, ignore=None))]
     fn jc<'py>(_py: Python<'py>,
                ref_arr: PyReadonlyArray3<'py, bool>,
                other_arr: PyReadonlyArray3<'py, bool>,
-    ) -> f32 {
+               roi: Option<PyReadonlyArray3<'py, bool>>,
+               ignore: Option<PyReadonlyArray3<'py, bool>>,
+    ) -> PyResult<f32> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        check_roi(ref_arr.shape(), &roi, &ignore)?;
+        let ref_mask = crate::bitmask::BitMask::from_view(ref_arr.as_array());
+        let other_mask = crate::bitmask::BitMask::from_view(other_arr.as_array());
+        let mut cm = crate::overlap::ConfusionMatrix::from_masks(ref_mask, other_mask);
+        if let Some(roi) = roi {
+            cm = cm.with_roi_mask(crate::bitmask::BitMask::from_view(roi.as_array()));
+        }
+        if let Some(ignore) = ignore {
+            cm = cm.with_ignore(&ignore.to_owned_array());
+        }
+        cm.execute();
+        Ok(cm.jc())
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "apl", signature = (ref_arr, other_arr, roi=None, ignore=None))]
+    fn apl<'py>(_py: Python<'py>,
+                ref_arr: PyReadonlyArray3<'py, bool>,
+                other_arr: PyReadonlyArray3<'py, bool>,
+                roi: Option<PyReadonlyArray3<'py, bool>>,
+                ignore: Option<PyReadonlyArray3<'py, bool>>,
+    ) -> PyResult<u32> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        check_roi(ref_arr.shape(), &roi, &ignore)?;
+        let ref_mask = crate::bitmask::BitMask::from_view(ref_arr.as_array());
+        let other_mask = crate::bitmask::BitMask::from_view(other_arr.as_array());
+        let mut apl = crate::apl::APL::from_masks(&ref_mask, &other_mask);
+        if let Some(roi) = roi {
+            apl = apl.with_roi_mask(crate::bitmask::BitMask::from_view(roi.as_array()));
+        }
+        if let Some(ignore) = ignore {
+            apl = apl.with_ignore(&ignore.to_owned_array());
+        }
+        apl.execute();
+        Ok(apl.apl())
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "slice_profile", signature = (ref_arr, other_arr, zyx_spacing, tolerance, axis=0))]
+    fn slice_profile<'py>(_py: Python<'py>,
+                          ref_arr: PyReadonlyArray3<'py, bool>,
+                          other_arr: PyReadonlyArray3<'py, bool>,
+                          zyx_spacing: Vec<f32>,
+                          tolerance: f32,
+                          axis: usize,
+    ) -> PyResult<&'py PyDict> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        check_axis(axis)?;
+        let ref_arr = ref_arr.to_owned_array();
+        let other_arr = other_arr.to_owned_array();
+        let mut profile = crate::profile::SliceProfile::new(&ref_arr, &other_arr, zyx_spacing, axis, tolerance);
+        profile.execute();
+
+        let dict = PyDict::new(_py);
+        dict.set_item("dc", PyArray1::from_slice(_py, profile.dc()))?;
+        dict.set_item("jc", PyArray1::from_slice(_py, profile.jc()))?;
+        dict.set_item("hd", PyArray1::from_slice(_py, profile.hd()))?;
+        dict.set_item("surface_dc", PyArray1::from_slice(_py, profile.surface_dc()))?;
+        dict.set_item("apl", PyArray1::from_slice(_py, profile.apl()))?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "restrict_to_reference_slices", signature = (ref_arr, other_arr, zyx_spacing, margin=0.0, slices=None, axis=0))]
+    fn restrict_to_reference_slices<'py>(_py: Python<'py>,
+                                         ref_arr: PyReadonlyArray3<'py, bool>,
+                                         other_arr: PyReadonlyArray3<'py, bool>,
+                                         zyx_spacing: Vec<f32>,
+                                         margin: f32,
+                                         slices: Option<Vec<usize>>,
+                                         axis: usize,
+    ) -> PyResult<MaskPair<'py>> {
+        check_axis(axis)?;
+        let ref_arr = ref_arr.to_owned_array();
+        let other_arr = other_arr.to_owned_array();
+        let slices = slices.unwrap_or_else(|| crate::utils::reference_slices(&ref_arr, axis, &zyx_spacing, margin));
+        Ok((crate::utils::restrict_to_slices(&ref_arr, &slices, axis).to_pyarray(_py),
+            crate::utils::restrict_to_slices(&other_arr, &slices, axis).to_pyarray(_py)))
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "reference_slice_mask", signature = (ref_arr, zyx_spacing, margin=0.0, slices=None, axis=0))]
+    fn reference_slice_mask<'py>(_py: Python<'py>,
+                                 ref_arr: PyReadonlyArray3<'py, bool>,
+                                 zyx_spacing: Vec<f32>,
+                                 margin: f32,
+                                 slices: Option<Vec<usize>>,
+                                 axis: usize,
+    ) -> PyResult<&'py PyArray<bool, Ix3>> {
+        check_axis(axis)?;
+        let ref_arr = ref_arr.to_owned_array();
+        let slices = slices.unwrap_or_else(|| crate::utils::reference_slices(&ref_arr, axis, &zyx_spacing, margin));
+        let shape = ref_arr.shape();
+        Ok(crate::utils::slice_mask([shape[0], shape[1], shape[2]], &slices, axis).to_pyarray(_py))
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "lesion_detection", signature = (ref_arr, other_arr, zyx_spacing, connectivity=26, match_by="overlap", max_distance=0.0))]
+    fn lesion_detection<'py>(_py: Python<'py>,
+                             ref_arr: PyReadonlyArray3<'py, bool>,
+                             other_arr: PyReadonlyArray3<'py, bool>,
+                             zyx_spacing: Vec<f32>,
+                             connectivity: u8,
+                             match_by: &str,
+                             max_distance: f32,
+    ) -> PyResult<&'py PyDict> {
+        let matching = match match_by {
+            "overlap" => crate::lesion::LesionMatching::Overlap,
+            "centroid" if max_distance > 0.0 => crate::lesion::LesionMatching::CentroidDistance(max_distance),
+            "centroid" => return Err(PyValueError::new_err(format!("max_distance must be positive when matching by centroid, got {}", max_distance))),
+            _ => return Err(PyValueError::new_err("match_by must be 'overlap' or 'centroid'")),
+        };
+        if ![6, 18, 26].contains(&connectivity) {
+            return Err(PyValueError::new_err(format!("connectivity must be 6, 18 or 26, got {}", connectivity)));
+        }
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        let ref_arr = ref_arr.to_owned_array();
+        let other_arr = other_arr.to_owned_array();
+        let mut detection = crate::lesion::LesionDetection::new(&ref_arr, &other_arr, zyx_spacing, connectivity, matching);
+        detection.execute();
+
+        let dict = PyDict::new(_py);
+        dict.set_item("tp", detection.tpos())?;
+        dict.set_item("fp", detection.fpos())?;
+        dict.set_item("fn", detection.fneg())?;
+        dict.set_item("sensitivity", detection.sensitivity())?;
+        dict.set_item("fp_per_case", detection.fp_per_case())?;
+        dict.set_item("f1", detection.f1())?;
+        dict.set_item("lesion_dc", PyArray1::from_slice(_py, detection.lesion_dc()))?;
+        dict.set_item("lesion_hd", PyArray1::from_slice(_py, detection.lesion_hd()))?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "panoptic_quality")]
+    fn panoptic_quality<'py>(_py: Python<'py>,
+                             ref_arr: PyReadonlyArray3<'py, u32>,
+                             other_arr: PyReadonlyArray3<'py, u32>,
+    ) -> PyResult<&'py PyDict> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        let ref_arr = ref_arr.to_owned_array();
+        let other_arr = other_arr.to_owned_array();
+        let mut pq = crate::panoptic::PanopticQuality::new(&ref_arr, &other_arr);
+        pq.execute();
+
+        let dict = PyDict::new(_py);
+        dict.set_item("pq", pq.pq())?;
+        dict.set_item("sq", pq.sq())?;
+        dict.set_item("rq", pq.rq())?;
+        dict.set_item("tp", pq.tpos())?;
+        dict.set_item("fp", pq.fpos())?;
+        dict.set_item("fn", pq.fneg())?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "soft_dc")]
+    fn soft_dc<'py>(_py: Python<'py>,
+                    ref_arr: PyReadonlyArray3<'py, bool>,
+                    prob_arr: PyReadonlyArray3<'py, f32>,
+    ) -> PyResult<f32> {
+        check_same_shape(ref_arr.shape(), prob_arr.shape())?;
+        let ref_arr = ref_arr.to_owned_array();
+        let prob_arr = prob_arr.to_owned_array();
+        let mut soft = crate::soft::SoftOverlap::new(&ref_arr, &prob_arr);
+        soft.execute();
+        Ok(soft.dc())
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "soft_jc")]
+    fn soft_jc<'py>(_py: Python<'py>,
+                    ref_arr: PyReadonlyArray3<'py, bool>,
+                    prob_arr: PyReadonlyArray3<'py, f32>,
+    ) -> PyResult<f32> {
+        check_same_shape(ref_arr.shape(), prob_arr.shape())?;
+        let ref_arr = ref_arr.to_owned_array();
+        let prob_arr = prob_arr.to_owned_array();
+        let mut soft = crate::soft::SoftOverlap::new(&ref_arr, &prob_arr);
+        soft.execute();
+        Ok(soft.jc())
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "soft_tversky")]
+    fn soft_tversky<'py>(_py: Python<'py>,
+                         ref_arr: PyReadonlyArray3<'py, bool>,
+                         prob_arr: PyReadonlyArray3<'py, f32>,
+                         alpha: f32,
+                         beta: f32,
+    ) -> PyResult<f32> {
+        check_same_shape(ref_arr.shape(), prob_arr.shape())?;
+        let ref_arr = ref_arr.to_owned_array();
+        let prob_arr = prob_arr.to_owned_array();
+        let mut soft = crate::soft::SoftOverlap::new(&ref_arr, &prob_arr);
+        soft.execute();
+        Ok(soft.tversky(&alpha, &beta))
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "threshold_sweep")]
+    fn threshold_sweep<'py>(_py: Python<'py>,
+                            ref_arr: PyReadonlyArray3<'py, bool>,
+                            prob_arr: PyReadonlyArray3<'py, f32>,
+                            zyx_spacing: Vec<f32>,
+                            thresholds: Vec<f32>,
+    ) -> PyResult<&'py PyDict> {
+        check_same_shape(ref_arr.shape(), prob_arr.shape())?;
+        let ref_arr = ref_arr.to_owned_array();
+        let prob_arr = prob_arr.to_owned_array();
+        let mut sweep = crate::soft::ThresholdSweep::new(&ref_arr, &prob_arr, zyx_spacing, thresholds);
+        sweep.execute();
+
+        let dict = PyDict::new(_py);
+        dict.set_item("thresholds", PyArray1::from_slice(_py, sweep.thresholds()))?;
+        dict.set_item("dc", PyArray1::from_vec(_py, sweep.dc()))?;
+        dict.set_item("jc", PyArray1::from_vec(_py, sweep.jc()))?;
+        dict.set_item("hd", PyArray1::from_slice(_py, sweep.hd()))?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "calibration", signature = (ref_arr, prob_arr, n_bins=10, zyx_spacing=None, band=None))]
+    fn calibration<'py>(_py: Python<'py>,
+                        ref_arr: PyReadonlyArray3<'py, bool>,
+                        prob_arr: PyReadonlyArray3<'py, f32>,
+                        n_bins: usize,
+                        zyx_spacing: Option<Vec<f32>>,
+                        band: Option<f32>,
+    ) -> PyResult<&'py PyDict> {
+        if n_bins == 0 {
+            return Err(PyValueError::new_err("n_bins must be positive"));
+        }
+        check_same_shape(ref_arr.shape(), prob_arr.shape())?;
+        let ref_arr = ref_arr.to_owned_array();
+        let prob_arr = prob_arr.to_owned_array();
+        let mut calibration = crate::calibration::Calibration::new(&ref_arr, &prob_arr, n_bins);
+        if let Some(band) = band {
+            let zyx_spacing = zyx_spacing.ok_or_else(|| PyValueError::new_err("zyx_spacing is required when band is given"))?;
+            calibration = calibration.with_roi(&crate::utils::surface_band(&ref_arr, &zyx_spacing, band));
+        }
+        calibration.execute();
+
+        let dict = PyDict::new(_py);
+        dict.set_item("ece", calibration.ece())?;
+        dict.set_item("brier", calibration.brier())?;
+        dict.set_item("nll", calibration.nll())?;
+        dict.set_item("bin_counts", PyArray1::from_vec(_py, calibration.bin_counts()))?;
+        dict.set_item("bin_confidence", PyArray1::from_vec(_py, calibration.bin_confidence()))?;
+        dict.set_item("bin_accuracy", PyArray1::from_vec(_py, calibration.bin_accuracy()))?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "majority_vote", signature = (masks, min_votes=None))]
+    fn majority_vote<'py>(_py: Python<'py>,
+                          masks: Vec<PyReadonlyArray3<'py, bool>>,
+                          min_votes: Option<usize>,
+    ) -> PyResult<&'py PyArray<bool, Ix3>> {
+        check_masks(&masks)?;
+        let masks: Vec<Array<bool, Ix3>> = masks.iter().map(|m| m.to_owned_array()).collect();
+        let consensus = match min_votes {
+            Some(min_votes) => crate::consensus::threshold_vote(&masks, min_votes),
+            None => crate::consensus::majority_vote(&masks),
+        };
+        Ok(consensus.to_pyarray(_py))
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "staple", signature = (masks, max_iter=100, tolerance=1e-5))]
+    fn staple<'py>(_py: Python<'py>,
+                   masks: Vec<PyReadonlyArray3<'py, bool>>,
+                   max_iter: usize,
+                   tolerance: f32,
+    ) -> PyResult<&'py PyDict> {
+        check_masks(&masks)?;
+        let masks: Vec<Array<bool, Ix3>> = masks.iter().map(|m| m.to_owned_array()).collect();
+        let mut staple = crate::consensus::Staple::new(&masks, max_iter, tolerance);
+        staple.execute();
+
+        let dict = PyDict::new(_py);
+        dict.set_item("consensus", staple.consensus().to_pyarray(_py))?;
+        dict.set_item("probabilities", staple.probabilities().to_pyarray(_py))?;
+        dict.set_item("sensitivity", PyArray1::from_slice(_py, staple.sensitivity()))?;
+        dict.set_item("specificity", PyArray1::from_slice(_py, staple.specificity()))?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "interobserver", signature = (masks, zyx_spacing, metrics, percentile=0.95, tolerance=1.0))]
+    fn interobserver<'py>(_py: Python<'py>,
+                          masks: Vec<PyReadonlyArray3<'py, bool>>,
+                          zyx_spacing: Vec<f32>,
+                          metrics: Vec<&str>,
+                          percentile: f32,
+                          tolerance: f32,
+    ) -> PyResult<&'py PyDict> {
+        check_masks(&masks)?;
+        let masks: Vec<Array<bool, Ix3>> = masks.iter().map(|m| m.to_owned_array()).collect();
+        let mut inter = crate::interobserver::InterObserver::new(&masks, zyx_spacing);
+        inter.execute();
+
+        let dict = PyDict::new(_py);
+        for metric in metrics {
+            let matrix = match metric {
+                "dc" => inter.dc(),
+                "jc" => inter.jc(),
+                "hd" => inter.hd(),
+                "hd_percentile" => inter.hd_percentile(&percentile),
+                "assd" => inter.assd(),
+                "surface_dc" => inter.surface_dc(&tolerance),
+                _ => return Err(PyValueError::new_err(format!("unknown metric '{}'", metric))),
+            };
+            dict.set_item(metric, matrix.to_pyarray(_py))?;
+        }
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "multiclass_overlap", signature = (ref_arr, other_arr, weighting="inverse_squared", include_background=false))]
+    fn multiclass_overlap<'py>(_py: Python<'py>,
+                               ref_arr: PyReadonlyArray3<'py, u32>,
+                               other_arr: PyReadonlyArray3<'py, u32>,
+                               weighting: &str,
+                               include_background: bool,
+    ) -> PyResult<&'py PyDict> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        let weighting = match weighting {
+            "uniform" => crate::overlap::LabelWeighting::Uniform,
+            "inverse" => crate::overlap::LabelWeighting::InverseVolume,
+            "inverse_squared" => crate::overlap::LabelWeighting::InverseSquaredVolume,
+            _ => return Err(PyValueError::new_err("weighting must be 'uniform', 'inverse' or 'inverse_squared'")),
+        };
         let ref_arr = ref_arr.to_owned_array();
         let other_arr = other_arr.to_owned_array();
-        let mut cm = crate::overlap::ConfusionMatrix::new(&ref_arr, &other_arr);
+        let mut cm = crate::overlap::MultiClassConfusionMatrix::new(&ref_arr, &other_arr, include_background);
         cm.execute();
-        cm.jc()
+
+        let dict = PyDict::new(_py);
+        dict.set_item("labels", PyArray1::from_slice(_py, cm.labels()))?;
+        dict.set_item("dc", PyArray1::from_vec(_py, cm.dc_per_label()))?;
+        dict.set_item("jc", PyArray1::from_vec(_py, cm.jc_per_label()))?;
+        dict.set_item("macro_dc", cm.macro_dc())?;
+        dict.set_item("micro_dc", cm.micro_dc())?;
+        dict.set_item("macro_jc", cm.macro_jc())?;
+        dict.set_item("micro_jc", cm.micro_jc())?;
+        dict.set_item("generalised_dc", cm.generalised_dc(&weighting))?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "volume")]
+    fn volume<'py>(_py: Python<'py>,
+                   arr: PyReadonlyArray3<'py, bool>,
+                   zyx_spacing: Vec<f32>,
+    ) -> PyResult<&'py PyDict> {
+        let volume = crate::volume::volume(&arr.to_owned_array(), &zyx_spacing);
+
+        let dict = PyDict::new(_py);
+        dict.set_item("volume_mm3", volume)?;
+        dict.set_item("volume_ml", volume / 1000.0)?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "volume_difference")]
+    fn volume_difference<'py>(_py: Python<'py>,
+                              ref_arr: PyReadonlyArray3<'py, bool>,
+                              other_arr: PyReadonlyArray3<'py, bool>,
+                              zyx_spacing: Vec<f32>,
+    ) -> PyResult<&'py PyDict> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        let ref_arr = ref_arr.to_owned_array();
+        let other_arr = other_arr.to_owned_array();
+        let mut vd = crate::volume::VolumeDifference::new(&ref_arr, &other_arr, zyx_spacing);
+        vd.execute();
+
+        let dict = PyDict::new(_py);
+        dict.set_item("ref_volume", vd.ref_volume())?;
+        dict.set_item("other_volume", vd.other_volume())?;
+        dict.set_item("abs_volume_difference", vd.abs_volume_difference())?;
+        dict.set_item("relative_volume_difference", vd.relative_volume_difference())?;
+        dict.set_item("volume_similarity", vd.volume_similarity())?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "shape_descriptors", signature = (arr, zyx_spacing, surface_area="voxel"))]
+    fn shape_descriptors<'py>(_py: Python<'py>,
+                              arr: PyReadonlyArray3<'py, bool>,
+                              zyx_spacing: Vec<f32>,
+                              surface_area: &str,
+    ) -> PyResult<&'py PyDict> {
+        let method = match surface_area {
+            "voxel" => crate::shape::SurfaceAreaMethod::VoxelFaces,
+            "mesh" => crate::shape::SurfaceAreaMethod::Mesh,
+            _ => return Err(PyValueError::new_err("surface_area must be 'voxel' or 'mesh'")),
+        };
+        let arr = arr.to_owned_array();
+        let mut shape = crate::shape::ShapeDescriptors::new(&arr, zyx_spacing).with_surface_area_method(method);
+        shape.execute();
+
+        let dict = PyDict::new(_py);
+        dict.set_item("volume", shape.volume())?;
+        dict.set_item("surface_area", shape.surface_area())?;
+        dict.set_item("sphericity", shape.sphericity())?;
+        dict.set_item("compactness", shape.compactness())?;
+        dict.set_item("elongation", shape.elongation())?;
+        dict.set_item("flatness", shape.flatness())?;
+        dict.set_item("centroid", shape.centroid().to_vec())?;
+        dict.set_item("principal_axes", shape.principal_axes().iter().map(|a| a.to_vec()).collect::<Vec<Vec<f32>>>())?;
+        dict.set_item("principal_lengths", shape.principal_lengths().to_vec())?;
+        dict.set_item("extent", shape.extent().to_vec())?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "centroid_displacement")]
+    fn centroid_displacement<'py>(_py: Python<'py>,
+                                  ref_arr: PyReadonlyArray3<'py, bool>,
+                                  other_arr: PyReadonlyArray3<'py, bool>,
+                                  zyx_spacing: Vec<f32>,
+    ) -> PyResult<&'py PyDict> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        let ref_arr = ref_arr.to_owned_array();
+        let other_arr = other_arr.to_owned_array();
+        let mut displacement = crate::displacement::CentroidDisplacement::new(&ref_arr, &other_arr, zyx_spacing);
+        displacement.execute();
+
+        let dict = PyDict::new(_py);
+        dict.set_item("distance", displacement.distance())?;
+        dict.set_item("displacement", displacement.displacement().to_vec())?;
+        dict.set_item("extent_difference", displacement.extent_difference().to_vec())?;
+        dict.set_item("boundary_shift", displacement.boundary_shift().iter().map(|b| b.to_vec()).collect::<Vec<Vec<f32>>>())?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "directional_surface_distance")]
+    fn directional_surface_distance<'py>(_py: Python<'py>,
+                                         ref_arr: PyReadonlyArray3<'py, bool>,
+                                         other_arr: PyReadonlyArray3<'py, bool>,
+                                         zyx_spacing: Vec<f32>,
+    ) -> PyResult<&'py PyDict> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        let ref_arr = ref_arr.to_owned_array();
+        let other_arr = other_arr.to_owned_array();
+        let mut directional = crate::directional::DirectionalSurfaceDistance::new(&ref_arr, &other_arr, zyx_spacing);
+        directional.execute();
+
+        let dict = PyDict::new(_py);
+        dict.set_item("directions", crate::directional::DIRECTIONS.to_vec())?;
+        dict.set_item("counts", directional.counts().to_vec())?;
+        dict.set_item("mean", directional.mean().to_vec())?;
+        dict.set_item("max", directional.max().to_vec())?;
+        dict.set_item("over_counts", directional.over_counts().to_vec())?;
+        dict.set_item("under_counts", directional.under_counts().to_vec())?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "skeletonize")]
+    fn skeletonize<'py>(_py: Python<'py>, arr: PyReadonlyArray3<'py, bool>) -> &'py PyArray<bool, Ix3> {
+        let arr = arr.to_owned_array();
+        crate::utils::skeletonize(&arr).to_pyarray(_py)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "cl_dice")]
+    fn cl_dice<'py>(_py: Python<'py>,
+                    ref_arr: PyReadonlyArray3<'py, bool>,
+                    other_arr: PyReadonlyArray3<'py, bool>,
+                    zyx_spacing: Vec<f32>,
+    ) -> PyResult<&'py PyDict> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        let ref_arr = ref_arr.to_owned_array();
+        let other_arr = other_arr.to_owned_array();
+        let mut cl = crate::centerline::ClDice::new(&ref_arr, &other_arr, zyx_spacing);
+        cl.execute();
+
+        let dict = PyDict::new(_py);
+        dict.set_item("cl_dice", cl.cl_dice())?;
+        dict.set_item("topology_precision", cl.topology_precision())?;
+        dict.set_item("topology_sensitivity", cl.topology_sensitivity())?;
+        dict.set_item("ref_length", cl.ref_length())?;
+        dict.set_item("other_length", cl.other_length())?;
+        dict.set_item("length_difference", cl.length_difference())?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "topology", signature = (arr, connectivity=26))]
+    fn topology<'py>(_py: Python<'py>,
+                     arr: PyReadonlyArray3<'py, bool>,
+                     connectivity: u8,
+    ) -> PyResult<&'py PyDict> {
+        if (connectivity != 6) & (connectivity != 26) {
+            return Err(PyValueError::new_err(format!("connectivity must be 6 or 26, got {}", connectivity)));
+        }
+        let arr = arr.to_owned_array();
+        let mut topology = crate::topology::Topology::new(&arr, connectivity);
+        topology.execute();
+
+        let dict = PyDict::new(_py);
+        dict.set_item("betti", topology.betti().to_vec())?;
+        dict.set_item("euler", topology.euler())?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "topology_error", signature = (ref_arr, other_arr, connectivity=26))]
+    fn topology_error<'py>(_py: Python<'py>,
+                           ref_arr: PyReadonlyArray3<'py, bool>,
+                           other_arr: PyReadonlyArray3<'py, bool>,
+                           connectivity: u8,
+    ) -> PyResult<&'py PyDict> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        if (connectivity != 6) & (connectivity != 26) {
+            return Err(PyValueError::new_err(format!("connectivity must be 6 or 26, got {}", connectivity)));
+        }
+        let ref_arr = ref_arr.to_owned_array();
+        let other_arr = other_arr.to_owned_array();
+        let mut error = crate::topology::TopologyError::new(&ref_arr, &other_arr, connectivity);
+        error.execute();
+
+        let dict = PyDict::new(_py);
+        dict.set_item("ref_betti", error.ref_betti().to_vec())?;
+        dict.set_item("other_betti", error.other_betti().to_vec())?;
+        dict.set_item("betti_error", error.betti_error().to_vec())?;
+        dict.set_item("euler_error", error.euler_error())?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "dilate")]
+    fn dilate<'py>(_py: Python<'py>, arr: PyReadonlyArray3<'py, bool>, zyx_spacing: Vec<f32>, radius: f32) -> &'py PyArray<bool, Ix3> {
+        let arr = arr.to_owned_array();
+        crate::morphology::dilate(&arr, &zyx_spacing, radius).to_pyarray(_py)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "erode")]
+    fn erode<'py>(_py: Python<'py>, arr: PyReadonlyArray3<'py, bool>, zyx_spacing: Vec<f32>, radius: f32) -> &'py PyArray<bool, Ix3> {
+        let arr = arr.to_owned_array();
+        crate::morphology::erode(&arr, &zyx_spacing, radius).to_pyarray(_py)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "opening")]
+    fn opening<'py>(_py: Python<'py>, arr: PyReadonlyArray3<'py, bool>, zyx_spacing: Vec<f32>, radius: f32) -> &'py PyArray<bool, Ix3> {
+        let arr = arr.to_owned_array();
+        crate::morphology::opening(&arr, &zyx_spacing, radius).to_pyarray(_py)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "closing")]
+    fn closing<'py>(_py: Python<'py>, arr: PyReadonlyArray3<'py, bool>, zyx_spacing: Vec<f32>, radius: f32) -> &'py PyArray<bool, Ix3> {
+        let arr = arr.to_owned_array();
+        crate::morphology::closing(&arr, &zyx_spacing, radius).to_pyarray(_py)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "expand_margin")]
+    fn expand_margin<'py>(_py: Python<'py>, arr: PyReadonlyArray3<'py, bool>, zyx_spacing: Vec<f32>, zyx_margins: [f32; 3]) -> &'py PyArray<bool, Ix3> {
+        let arr = arr.to_owned_array();
+        crate::morphology::expand_margin(&arr, &zyx_spacing, zyx_margins).to_pyarray(_py)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "contract_margin")]
+    fn contract_margin<'py>(_py: Python<'py>, arr: PyReadonlyArray3<'py, bool>, zyx_spacing: Vec<f32>, zyx_margins: [f32; 3]) -> &'py PyArray<bool, Ix3> {
+        let arr = arr.to_owned_array();
+        crate::morphology::contract_margin(&arr, &zyx_spacing, zyx_margins).to_pyarray(_py)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "fill_holes")]
+    fn fill_holes<'py>(_py: Python<'py>, arr: PyReadonlyArray3<'py, bool>) -> &'py PyArray<bool, Ix3> {
+        let arr = arr.to_owned_array();
+        crate::morphology::fill_holes(&arr).to_pyarray(_py)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "largest_component", signature = (arr, connectivity=26))]
+    fn largest_component<'py>(_py: Python<'py>, arr: PyReadonlyArray3<'py, bool>, connectivity: u8) -> PyResult<&'py PyArray<bool, Ix3>> {
+        if ![6, 18, 26].contains(&connectivity) {
+            return Err(PyValueError::new_err(format!("connectivity must be 6, 18 or 26, got {}", connectivity)));
+        }
+        let arr = arr.to_owned_array();
+        Ok(crate::morphology::largest_component(&arr, connectivity).to_pyarray(_py))
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "boundary_iou")]
+    fn boundary_iou<'py>(_py: Python<'py>,
+                         ref_arr: PyReadonlyArray3<'py, bool>,
+                         other_arr: PyReadonlyArray3<'py, bool>,
+                         zyx_spacing: Vec<f32>,
+                         width: f32,
+    ) -> PyResult<f32> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        let ref_arr = ref_arr.to_owned_array();
+        let other_arr = other_arr.to_owned_array();
+        let mut biou = crate::boundary::BoundaryIoU::new(&ref_arr, &other_arr, zyx_spacing, width);
+        biou.execute();
+        Ok(biou.biou())
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "boundary_f_score")]
+    fn boundary_f_score<'py>(_py: Python<'py>,
+                             ref_arr: PyReadonlyArray3<'py, bool>,
+                             other_arr: PyReadonlyArray3<'py, bool>,
+                             zyx_spacing: Vec<f32>,
+                             tolerance: f32,
+    ) -> PyResult<&'py PyDict> {
+        check_same_shape(ref_arr.shape(), other_arr.shape())?;
+        let ref_arr = ref_arr.to_owned_array();
+        let other_arr = other_arr.to_owned_array();
+        let mut bf = crate::boundary::BoundaryFScore::new(&ref_arr, &other_arr, zyx_spacing);
+        bf.execute();
+
+        let dict = PyDict::new(_py);
+        dict.set_item("f_score", bf.f_score(&tolerance))?;
+        dict.set_item("precision", bf.precision(&tolerance))?;
+        dict.set_item("recall", bf.recall(&tolerance))?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "mesh", signature = (arr, zyx_spacing, other_arr=None))]
+    fn mesh<'py>(_py: Python<'py>,
+                 arr: PyReadonlyArray3<'py, bool>,
+                 zyx_spacing: Vec<f32>,
+                 other_arr: Option<PyReadonlyArray3<'py, bool>>,
+    ) -> PyResult<&'py PyDict> {
+        if let Some(other_arr) = &other_arr {
+            check_same_shape(arr.shape(), other_arr.shape())?;
+        }
+        let arr = arr.to_owned_array();
+        let mut mesh = crate::mesh::Mesh::new(&arr, zyx_spacing);
+        mesh.execute();
+        if let Some(other_arr) = other_arr {
+            mesh.color_by_distance(&other_arr.to_owned_array());
+        }
+
+        let vertices: Vec<f32> = mesh.vertices().iter().flatten().cloned().collect();
+        let faces: Vec<u32> = mesh.faces().iter().flatten().cloned().collect();
+        let dict = PyDict::new(_py);
+        dict.set_item("vertices", Array::from_shape_vec((mesh.vertices().len(), 3), vertices).unwrap().to_pyarray(_py))?;
+        dict.set_item("faces", Array::from_shape_vec((mesh.faces().len(), 3), faces).unwrap().to_pyarray(_py))?;
+        if let Some(scalars) = mesh.scalars() {
+            dict.set_item("scalars", PyArray1::from_slice(_py, scalars))?;
+        }
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "write_mesh", signature = (arr, zyx_spacing, path, other_arr=None))]
+    fn write_mesh<'py>(_py: Python<'py>,
+                       arr: PyReadonlyArray3<'py, bool>,
+                       zyx_spacing: Vec<f32>,
+                       path: &str,
+                       other_arr: Option<PyReadonlyArray3<'py, bool>>,
+    ) -> PyResult<()> {
+        if let Some(other_arr) = &other_arr {
+            check_same_shape(arr.shape(), other_arr.shape())?;
+        }
+        let arr = arr.to_owned_array();
+        let mut mesh = crate::mesh::Mesh::new(&arr, zyx_spacing);
+        mesh.execute();
+        if let Some(other_arr) = other_arr {
+            mesh.color_by_distance(&other_arr.to_owned_array());
+        }
+        let path = std::path::Path::new(path);
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref() {
+            Some("stl") => mesh.write_stl(path)?,
+            Some("ply") => mesh.write_ply(path)?,
+            Some("obj") => mesh.write_obj(path)?,
+            _ => return Err(PyValueError::new_err("path must end in .stl, .ply or .obj")),
+        }
+        Ok(())
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "read_stl")]
+    fn read_stl<'py>(_py: Python<'py>, path: &str) -> PyResult<&'py PyDict> {
+        let mesh = crate::mesh::Mesh::read_stl(std::path::Path::new(path))?;
+        let vertices: Vec<f32> = mesh.vertices().iter().flatten().cloned().collect();
+        let faces: Vec<u32> = mesh.faces().iter().flatten().cloned().collect();
+        let dict = PyDict::new(_py);
+        dict.set_item("vertices", Array::from_shape_vec((mesh.vertices().len(), 3), vertices).unwrap().to_pyarray(_py))?;
+        dict.set_item("faces", Array::from_shape_vec((mesh.faces().len(), 3), faces).unwrap().to_pyarray(_py))?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "mesh_distance", signature = (ref_vertices, other_vertices, ref_faces=None, other_faces=None, percentile=0.95, tolerance=1.0))]
+    fn mesh_distance<'py>(_py: Python<'py>,
+                          ref_vertices: PyReadonlyArray2<'py, f32>,
+                          other_vertices: PyReadonlyArray2<'py, f32>,
+                          ref_faces: Option<PyReadonlyArray2<'py, u32>>,
+                          other_faces: Option<PyReadonlyArray2<'py, u32>>,
+                          percentile: f32,
+                          tolerance: f32,
+    ) -> PyResult<&'py PyDict> {
+        fn to_mesh(vertices: PyReadonlyArray2<f32>, faces: Option<PyReadonlyArray2<u32>>) -> PyResult<crate::mesh::Mesh> {
+            let vertices = vertices.as_array();
+            if (vertices.shape()[0] == 0) | (vertices.shape()[1] != 3) {
+                return Err(PyValueError::new_err("vertices must have shape (n, 3) with n > 0"));
+            }
+            let vertices: Vec<[f32; 3]> = vertices.rows().into_iter().map(|r| [r[0], r[1], r[2]]).collect();
+            let faces: Vec<[u32; 3]> = match faces {
+                Some(faces) => {
+                    let faces = faces.as_array();
+                    if (faces.shape()[1] != 3) | faces.iter().any(|i| *i as usize >= vertices.len()) {
+                        return Err(PyValueError::new_err("faces must have shape (m, 3) and index into vertices"));
+                    }
+                    faces.rows().into_iter().map(|r| [r[0], r[1], r[2]]).collect()
+                }
+                None => Vec::new(),
+            };
+            Ok(crate::mesh::Mesh::from_triangles(vertices, faces))
+        }
+        let ref_mesh = to_mesh(ref_vertices, ref_faces)?;
+        let other_mesh = to_mesh(other_vertices, other_faces)?;
+
+        let mut hd_map = crate::mesh::mesh_distance_map(&ref_mesh, &other_mesh);
+        let dict = PyDict::new(_py);
+        dict.set_item("hd", hd_map.hd())?;
+        dict.set_item("hd_percentile", hd_map.hd_percentile(&percentile))?;
+        dict.set_item("assd", hd_map.assd())?;
+        dict.set_item("surface_dc", hd_map.surface_dc(&tolerance))?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "rle_encode")]
+    fn rle_encode<'py>(_py: Python<'py>, arr: PyReadonlyArray3<'py, bool>) -> &'py PyArray1<u32> {
+        let arr = arr.to_owned_array();
+        PyArray1::from_slice(_py, crate::rle::RleMask::from_array(&arr).counts())
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "rle_decode")]
+    fn rle_decode<'py>(_py: Python<'py>, counts: Vec<u32>, shape: [usize; 3]) -> PyResult<&'py PyArray<bool, Ix3>> {
+        let rle = crate::rle::RleMask::from_counts(shape, counts)
+            .ok_or_else(|| PyValueError::new_err("counts must add up to the number of voxels"))?;
+        Ok(rle.to_array().to_pyarray(_py))
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "rle_encode_slices", signature = (arr, order="C"))]
+    fn rle_encode_slices<'py>(_py: Python<'py>, arr: PyReadonlyArray3<'py, bool>, order: &str) -> PyResult<Vec<Vec<u32>>> {
+        let order = parse_slice_order(order)?;
+        let arr = arr.to_owned_array();
+        Ok(crate::rle::RleMask::from_array(&arr).slices(order))
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "rle_join_slices", signature = (slices, shape, order="C"))]
+    fn rle_join_slices<'py>(_py: Python<'py>, slices: Vec<Vec<u32>>, shape: [usize; 3], order: &str) -> PyResult<&'py PyArray1<u32>> {
+        let rle = crate::rle::RleMask::from_slices(shape, &slices, parse_slice_order(order)?)
+            .ok_or_else(|| PyValueError::new_err("need one RLE per z-slice, each adding up to the slice size"))?;
+        Ok(PyArray1::from_slice(_py, rle.counts()))
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "rle_overlap")]
+    fn rle_overlap<'py>(_py: Python<'py>,
+                        ref_counts: Vec<u32>,
+                        other_counts: Vec<u32>,
+                        shape: [usize; 3],
+    ) -> PyResult<&'py PyDict> {
+        let error = || PyValueError::new_err("counts must add up to the number of voxels");
+        let ref_rle = crate::rle::RleMask::from_counts(shape, ref_counts).ok_or_else(error)?;
+        let other_rle = crate::rle::RleMask::from_counts(shape, other_counts).ok_or_else(error)?;
+        let cm = crate::rle::confusion_matrix(&ref_rle, &other_rle);
+        let dict = PyDict::new(_py);
+        dict.set_item("dc", cm.dc())?;
+        dict.set_item("jc", cm.jc())?;
+        dict.set_item("tpos", cm.tpos())?;
+        dict.set_item("tneg", cm.tneg())?;
+        dict.set_item("fpos", cm.fpos())?;
+        dict.set_item("fneg", cm.fneg())?;
+        Ok(dict)
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "rle_apl")]
+    fn rle_apl(ref_counts: Vec<u32>, other_counts: Vec<u32>, shape: [usize; 3]) -> PyResult<u32> {
+        let error = || PyValueError::new_err("counts must add up to the number of voxels");
+        let ref_rle = crate::rle::RleMask::from_counts(shape, ref_counts).ok_or_else(error)?;
+        let other_rle = crate::rle::RleMask::from_counts(shape, other_counts).ok_or_else(error)?;
+        Ok(crate::rle::apl(&ref_rle, &other_rle).apl())
+    }
+    #[pyfn(m)]
+    #[pyo3(name = "reference_surface")]
+    fn reference_surface<'py>(_py: Python<'py>, arr: PyReadonlyArray3<'py, bool>, zyx_spacing: Vec<f32>) -> PyResult<ReferenceSurface> {
+        if zyx_spacing.len() != 3 {
+            return Err(PyValueError::new_err("zyx_spacing must have 3 elements"));
+        }
+        let arr = arr.to_owned_array();
+        Ok(ReferenceSurface { inner: crate::reference::ReferenceSurface::new(&arr, zyx_spacing) })
     }
+    m.add_class::<ReferenceSurface>()?;
     Ok(())
 
 }
\ No newline at end of file
@@ -0,0 +1,578 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use ndarray::{s, Array, Array3, Ix3};
+use crate::distance::{HausdorffMapDirected, HausdorffMapUndirected};
+use crate::kdtree::KdTree;
+
+/// Cube corners as (z, y, x) offsets.
+const CORNERS: [[usize; 3]; 8] = [
+    [0, 0, 0], [0, 0, 1], [0, 1, 1], [0, 1, 0],
+    [1, 0, 0], [1, 0, 1], [1, 1, 1], [1, 1, 0],
+];
+/// Decomposition of the cube into six tetrahedra around the diagonal 0-6.
+const TETRAHEDRA: [[usize; 4]; 6] = [
+    [0, 5, 1, 6], [0, 1, 2, 6], [0, 2, 3, 6],
+    [0, 3, 7, 6], [0, 7, 4, 6], [0, 4, 5, 6],
+];
+
+/// Triangle surface of a mask in mm (vertices in zyx order), extracted with marching
+/// cubes using the tetrahedral decomposition of each cube, which has no ambiguous cases.
+/// The mask is treated as a 0/1 field with iso-level 0.5, so every vertex sits halfway
+/// between a foreground voxel and a background neighbour. Faces are oriented outwards.
+pub struct Mesh {
+    arr: Array<bool, Ix3>,
+    zyx_spacing: Vec<f32>,
+    vertices: Vec<[f32; 3]>,
+    faces: Vec<[u32; 3]>,
+    vertex_voxels: Vec<[usize; 3]>,
+    scalars: Option<Vec<f32>>,
+}
+
+fn sub(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+fn cross(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+fn dot(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+fn distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    let d = sub(a, b);
+    dot(&d, &d).sqrt()
+}
+
+/// Distance from `p` to the closest point of triangle `abc` (Ericson, Real-Time Collision Detection, 5.1.5).
+pub fn point_triangle_distance(p: &[f32; 3], a: &[f32; 3], b: &[f32; 3], c: &[f32; 3]) -> f32 {
+    let ab = sub(b, a);
+    let ac = sub(c, a);
+    let ap = sub(p, a);
+    let d1 = dot(&ab, &ap);
+    let d2 = dot(&ac, &ap);
+    if (d1 <= 0.0) & (d2 <= 0.0) {
+        return distance(p, a);
+    }
+    let bp = sub(p, b);
+    let d3 = dot(&ab, &bp);
+    let d4 = dot(&ac, &bp);
+    if (d3 >= 0.0) & (d4 <= d3) {
+        return distance(p, b);
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if (vc <= 0.0) & (d1 >= 0.0) & (d3 <= 0.0) {
+        let v = d1 / (d1 - d3);
+        return distance(p, &[0, 1, 2].map(|i| a[i] + v * ab[i]));
+    }
+    let cp = sub(p, c);
+    let d5 = dot(&ab, &cp);
+    let d6 = dot(&ac, &cp);
+    if (d6 >= 0.0) & (d5 <= d6) {
+        return distance(p, c);
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if (vb <= 0.0) & (d2 >= 0.0) & (d6 <= 0.0) {
+        let w = d2 / (d2 - d6);
+        return distance(p, &[0, 1, 2].map(|i| a[i] + w * ac[i]));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if (va <= 0.0) & ((d4 - d3) >= 0.0) & ((d5 - d6) >= 0.0) {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return distance(p, &[0, 1, 2].map(|i| b[i] + w * (c[i] - b[i])));
+    }
+    let denom = 1.0 / (va + vb + vc);
+    let v = vb * denom;
+    let w = vc * denom;
+    distance(p, &[0, 1, 2].map(|i| a[i] + ab[i] * v + ac[i] * w))
+}
+
+impl Mesh {
+    pub fn new(arr: &Array<bool, Ix3>, zyx_spacing: Vec<f32>) -> Mesh {
+        Mesh {
+            arr: arr.clone(),
+            zyx_spacing,
+            vertices: Vec::new(),
+            faces: Vec::new(),
+            vertex_voxels: Vec::new(),
+            scalars: None,
+        }
+    }
+    /// Mesh from existing geometry (vertices in mm, zyx order). Without faces it is a point cloud.
+    pub fn from_triangles(vertices: Vec<[f32; 3]>, faces: Vec<[u32; 3]>) -> Mesh {
+        Mesh {
+            arr: Array::from_elem((0, 0, 0), false),
+            zyx_spacing: Vec::new(),
+            vertices,
+            faces,
+            vertex_voxels: Vec::new(),
+            scalars: None,
+        }
+    }
+    /// Reads a binary or ASCII STL file, merging coincident vertices.
+    pub fn read_stl(path: &Path) -> std::io::Result<Mesh> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+
+        // ASCII files start with "solid", but so do some binary headers; trust the size check
+        let n_binary = if bytes.len() >= 84 { u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize } else { 0 };
+        let is_binary = (bytes.len() >= 84) && (bytes.len() == 84 + 50 * n_binary);
+        let mut corners: Vec<[f32; 3]> = Vec::new();
+        if is_binary {
+            for t in 0..n_binary {
+                let offset = 84 + 50 * t + 12;
+                for v in 0..3 {
+                    let xyz = [0, 1, 2].map(|i| {
+                        let start = offset + 12 * v + 4 * i;
+                        f32::from_le_bytes([bytes[start], bytes[start + 1], bytes[start + 2], bytes[start + 3]])
+                    });
+                    corners.push([xyz[2], xyz[1], xyz[0]]);
+                }
+            }
+        } else {
+            for line in String::from_utf8_lossy(&bytes).lines() {
+                let mut tokens = line.split_whitespace();
+                if tokens.next() != Some("vertex") {
+                    continue;
+                }
+                let xyz: Vec<f32> = tokens.map(|t| t.parse::<f32>()).collect::<Result<_, _>>()
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+                if xyz.len() != 3 {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "vertex needs 3 coordinates"));
+                }
+                corners.push([xyz[2], xyz[1], xyz[0]]);
+            }
+            if !corners.len().is_multiple_of(3) {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "incomplete facet"));
+            }
+        }
+
+        let mut vertices = Vec::new();
+        let mut indices = HashMap::<[u32; 3], u32>::new();
+        let ids: Vec<u32> = corners.iter()
+            .map(|c| *indices.entry(c.map(f32::to_bits)).or_insert_with(|| {
+                vertices.push(*c);
+                vertices.len() as u32 - 1
+            }))
+            .collect();
+        let faces = ids.chunks(3).map(|f| [f[0], f[1], f[2]]).collect();
+        Ok(Mesh::from_triangles(vertices, faces))
+    }
+    /// Physical position of padded grid point `p`.
+    fn position(&self, p: &[usize; 3]) -> [f32; 3] {
+        [0, 1, 2].map(|i| (p[i] as f32 - 1.0) * self.zyx_spacing[i])
+    }
+    pub fn execute(&mut self) {
+        let shape = self.arr.shape();
+        let mut padded = Array3::<bool>::from_elem((shape[0] + 2, shape[1] + 2, shape[2] + 2), false);
+        padded.slice_mut(s![1..shape[0] + 1, 1..shape[1] + 1, 1..shape[2] + 1]).assign(&self.arr);
+        let padded_shape = [shape[0] + 2, shape[1] + 2, shape[2] + 2];
+
+        // Vertices are shared between triangles through the grid edge they lie on
+        let mut edge_vertices = HashMap::<([usize; 3], [usize; 3]), u32>::new();
+        for z in 0..padded_shape[0] - 1 {
+            for y in 0..padded_shape[1] - 1 {
+                for x in 0..padded_shape[2] - 1 {
+                    let points = CORNERS.map(|c| [z + c[0], y + c[1], x + c[2]]);
+                    let inside = points.map(|p| padded[p]);
+                    if inside.iter().all(|v| *v) | inside.iter().all(|v| !*v) {
+                        continue;
+                    }
+                    for tetrahedron in TETRAHEDRA {
+                        let (ins, outs): (Vec<usize>, Vec<usize>) = tetrahedron.iter().partition(|c| inside[**c]);
+                        let crossings: Vec<(usize, usize)> = match (ins.len(), outs.len()) {
+                            (1, 3) => outs.iter().map(|o| (ins[0], *o)).collect(),
+                            (3, 1) => ins.iter().map(|i| (*i, outs[0])).collect(),
+                            (2, 2) => vec![(ins[0], outs[0]), (ins[0], outs[1]), (ins[1], outs[1]), (ins[1], outs[0])],
+                            _ => continue,
+                        };
+                        let mut polygon = Vec::with_capacity(4);
+                        for (i, o) in &crossings {
+                            let key = (points[*i], points[*o]);
+                            let idx = match edge_vertices.get(&key) {
+                                Some(idx) => *idx,
+                                None => {
+                                    let a = self.position(&points[*i]);
+                                    let b = self.position(&points[*o]);
+                                    self.vertices.push([0, 1, 2].map(|d| (a[d] + b[d]) / 2.0));
+                                    self.vertex_voxels.push([points[*i][0] - 1, points[*i][1] - 1, points[*i][2] - 1]);
+                                    edge_vertices.insert(key, self.vertices.len() as u32 - 1);
+                                    self.vertices.len() as u32 - 1
+                                }
+                            };
+                            polygon.push(idx);
+                        }
+                        // Orient each triangle so its normal points from the inside to the outside corner
+                        let outward = sub(&self.position(&points[crossings[0].1]), &self.position(&points[crossings[0].0]));
+                        for k in 1..polygon.len() - 1 {
+                            let mut face = [polygon[0], polygon[k], polygon[k + 1]];
+                            let v = face.map(|f| self.vertices[f as usize]);
+                            let normal = cross(&sub(&v[1], &v[0]), &sub(&v[2], &v[0]));
+                            if dot(&normal, &outward) < 0.0 {
+                                face.swap(1, 2);
+                            }
+                            self.faces.push(face);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    /// Sets the per-vertex scalars to the distance (mm) from the mask surface to the surface
+    /// of `other_arr`, as computed by `HausdorffMapDirected`. Each vertex takes the value of
+    /// the foreground voxel it was interpolated from.
+    pub fn color_by_distance(&mut self, other_arr: &Array<bool, Ix3>) {
+        let mut hd_map = HausdorffMapDirected::new(&self.arr, other_arr, self.zyx_spacing.clone());
+        hd_map.execute();
+        let voxel_distances: HashMap<[usize; 3], f32> = hd_map.ref_points().into_iter()
+            .zip(hd_map.distances().iter().cloned())
+            .collect();
+        self.scalars = Some(self.vertex_voxels.iter()
+            .map(|v| voxel_distances.get(v).cloned().unwrap_or(f32::NAN))
+            .collect());
+    }
+    pub fn vertices(&self) -> &[[f32; 3]] {
+        &self.vertices
+    }
+    pub fn faces(&self) -> &[[u32; 3]] {
+        &self.faces
+    }
+    /// Total triangle area in mm².
+    pub fn surface_area(&self) -> f32 {
+        self.faces.iter()
+            .map(|f| {
+                let v = f.map(|i| self.vertices[i as usize]);
+                let normal = cross(&sub(&v[1], &v[0]), &sub(&v[2], &v[0]));
+                (dot(&normal, &normal) as f64).sqrt() / 2.0
+            })
+            .sum::<f64>() as f32
+    }
+    pub fn scalars(&self) -> Option<&[f32]> {
+        self.scalars.as_deref()
+    }
+    /// Binary STL. Coordinates are written in xyz order.
+    pub fn write_stl(&self, path: &Path) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&[0_u8; 80])?;
+        writer.write_all(&(self.faces.len() as u32).to_le_bytes())?;
+        for face in &self.faces {
+            let v = face.map(|f| self.vertices[f as usize]);
+            let normal = cross(&sub(&v[1], &v[0]), &sub(&v[2], &v[0]));
+            let length = dot(&normal, &normal).sqrt().max(f32::MIN_POSITIVE);
+            for c in [normal[2], normal[1], normal[0]] {
+                writer.write_all(&(c / length).to_le_bytes())?;
+            }
+            for vertex in v {
+                for c in [vertex[2], vertex[1], vertex[0]] {
+                    writer.write_all(&c.to_le_bytes())?;
+                }
+            }
+            writer.write_all(&0_u16.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+    /// ASCII PLY, with the per-vertex scalars as a `distance` property if present.
+    /// Coordinates are written in xyz order.
+    pub fn write_ply(&self, path: &Path) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "ply\nformat ascii 1.0")?;
+        writeln!(writer, "element vertex {}", self.vertices.len())?;
+        writeln!(writer, "property float x\nproperty float y\nproperty float z")?;
+        if self.scalars.is_some() {
+            writeln!(writer, "property float distance")?;
+        }
+        writeln!(writer, "element face {}", self.faces.len())?;
+        writeln!(writer, "property list uchar int vertex_indices\nend_header")?;
+        for (i, v) in self.vertices.iter().enumerate() {
+            match &self.scalars {
+                Some(scalars) => writeln!(writer, "{} {} {} {}", v[2], v[1], v[0], scalars[i])?,
+                None => writeln!(writer, "{} {} {}", v[2], v[1], v[0])?,
+            }
+        }
+        for f in &self.faces {
+            writeln!(writer, "3 {} {} {}", f[0], f[1], f[2])?;
+        }
+        writer.flush()
+    }
+    /// Wavefront OBJ (geometry only). Coordinates are written in xyz order.
+    pub fn write_obj(&self, path: &Path) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for v in &self.vertices {
+            writeln!(writer, "v {} {} {}", v[2], v[1], v[0])?;
+        }
+        for f in &self.faces {
+            writeln!(writer, "f {} {} {}", f[0] + 1, f[1] + 1, f[2] + 1)?;
+        }
+        writer.flush()
+    }
+}
+
+/// Triangles per leaf of a `TriangleBvh`.
+const LEAF_SIZE: usize = 4;
+
+struct BvhNode {
+    min: [f32; 3],
+    max: [f32; 3],
+    lo: usize,
+    hi: usize,
+    children: Option<(usize, usize)>,
+}
+
+/// Distance from `p` to the axis-aligned box `min`-`max` (0 inside).
+fn box_distance(p: &[f32; 3], min: &[f32; 3], max: &[f32; 3]) -> f32 {
+    [0, 1, 2].map(|i| (min[i] - p[i]).max(p[i] - max[i]).max(0.0).powi(2)).iter().sum::<f32>().sqrt()
+}
+
+/// Bounding volume hierarchy over the triangles of a mesh for closest-point queries.
+/// Every node holds the bounding box of a range of triangles; ranges are split at the
+/// median centroid along the longest box axis, like `KdTree`.
+struct TriangleBvh<'a> {
+    vertices: &'a [[f32; 3]],
+    faces: Vec<[u32; 3]>,
+    nodes: Vec<BvhNode>,
+}
+
+impl<'a> TriangleBvh<'a> {
+    fn new(vertices: &'a [[f32; 3]], faces: &[[u32; 3]]) -> TriangleBvh<'a> {
+        let mut bvh = TriangleBvh { vertices, faces: faces.to_vec(), nodes: Vec::new() };
+        if !faces.is_empty() {
+            bvh.build(0, faces.len());
+        }
+        bvh
+    }
+    fn build(&mut self, lo: usize, hi: usize) -> usize {
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for v in self.faces[lo..hi].iter().flatten().map(|i| &self.vertices[*i as usize]) {
+            for i in 0..3 {
+                min[i] = min[i].min(v[i]);
+                max[i] = max[i].max(v[i]);
+            }
+        }
+        let node = self.nodes.len();
+        self.nodes.push(BvhNode { min, max, lo, hi, children: None });
+        if hi - lo > LEAF_SIZE {
+            let axis = (0..3).max_by(|a, b| (max[*a] - min[*a]).partial_cmp(&(max[*b] - min[*b])).unwrap()).unwrap();
+            let vertices = self.vertices;
+            let centroid = |f: &[u32; 3]| f.iter().map(|i| vertices[*i as usize][axis]).sum::<f32>();
+            let mid = (lo + hi) / 2;
+            self.faces[lo..hi].select_nth_unstable_by(mid - lo, |a, b| centroid(a).partial_cmp(&centroid(b)).unwrap());
+            let left = self.build(lo, mid);
+            let right = self.build(mid, hi);
+            self.nodes[node].children = Some((left, right));
+        }
+        node
+    }
+    /// Distance from `p` to the closest triangle (infinite without triangles).
+    fn distance(&self, p: &[f32; 3]) -> f32 {
+        let mut best = f32::INFINITY;
+        if !self.nodes.is_empty() {
+            self.search(0, p, &mut best);
+        }
+        best
+    }
+    /// Visits the nearer child first and skips boxes further away than the best triangle.
+    fn search(&self, node: usize, p: &[f32; 3], best: &mut f32) {
+        let node = &self.nodes[node];
+        match node.children {
+            None => {
+                for f in &self.faces[node.lo..node.hi] {
+                    let [a, b, c] = f.map(|i| &self.vertices[i as usize]);
+                    *best = best.min(point_triangle_distance(p, a, b, c));
+                }
+            }
+            Some((left, right)) => {
+                let box_dist = |n: usize| box_distance(p, &self.nodes[n].min, &self.nodes[n].max);
+                let (d_left, d_right) = (box_dist(left), box_dist(right));
+                let ((near, d_near), (far, d_far)) = if d_left <= d_right {
+                    ((left, d_left), (right, d_right))
+                } else {
+                    ((right, d_right), (left, d_left))
+                };
+                if d_near <= *best {
+                    self.search(near, p, best);
+                }
+                if d_far <= *best {
+                    self.search(far, p, best);
+                }
+            }
+        }
+    }
+}
+
+/// Distance of every vertex of `points` to the surface of `target`: the closest triangle
+/// (searched in a `TriangleBvh`), or the closest vertex (searched in a `KdTree`) if
+/// `target` has no faces. 999999 if `target` has no vertices, as for an empty surface in
+/// `HausdorffMapDirected`.
+fn distances_to(points: &Mesh, target: &Mesh) -> Vec<f32> {
+    if target.vertices.is_empty() {
+        return vec![999999.0; points.vertices.len()];
+    }
+    if target.faces.is_empty() {
+        let tree = KdTree::new(target.vertices.clone());
+        return points.vertices.iter().map(|p| tree.nearest(p).unwrap().1).collect();
+    }
+    let bvh = TriangleBvh::new(&target.vertices, &target.faces);
+    points.vertices.iter().map(|p| bvh.distance(p)).collect()
+}
+
+/// Surface distances between two meshes or point clouds without voxelizing. Distances are
+/// taken from the vertices of each mesh to the surface of the other, so the result is
+/// only as dense as the vertex sampling.
+pub fn mesh_distance_map(ref_mesh: &Mesh, other_mesh: &Mesh) -> HausdorffMapUndirected {
+    HausdorffMapUndirected::from_distances(distances_to(ref_mesh, other_mesh), distances_to(other_mesh, ref_mesh))
+}
+
+#[cfg(test)]
+mod test_mesh {
+    use std::collections::HashMap;
+    use ndarray::{s, Array3};
+    use crate::mesh::{mesh_distance_map, point_triangle_distance, Mesh, TriangleBvh};
+
+    /// Unique temporary file for one test, removed when dropped.
+    struct TempFile(std::path::PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            TempFile(std::env::temp_dir().join(format!("volumetrics_{}_{}", std::process::id(), name)))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn generate_mesh() -> Mesh {
+        let mut src = Array3::<bool>::from_elem([4, 5, 6], false);
+        src.slice_mut(s![0..3, 1..4, 1..5]).fill(true);
+        src[[3, 2, 2]] = true;
+        let mut mesh = Mesh::new(&src, vec![2.0, 1.0, 0.5]);
+        mesh.execute();
+        mesh
+    }
+
+    #[test]
+    fn test_mesh_is_closed_and_oriented() {
+        let mesh = generate_mesh();
+        assert!(!mesh.faces().is_empty());
+
+        // Every directed edge must be matched by its reverse exactly once
+        let mut edges = HashMap::<(u32, u32), i32>::new();
+        for f in mesh.faces() {
+            for k in 0..3 {
+                *edges.entry((f[k], f[(k + 1) % 3])).or_insert(0) += 1;
+            }
+        }
+        for ((a, b), count) in &edges {
+            assert_eq!(*count, 1);
+            assert_eq!(edges.get(&(*b, *a)), Some(&1));
+        }
+        let n_edges = edges.len() / 2;
+        assert_eq!(mesh.vertices().len() as i64 - n_edges as i64 + mesh.faces().len() as i64, 2);
+
+        // Outward orientation gives a positive enclosed volume
+        let mut volume = 0.0;
+        for f in mesh.faces() {
+            let [a, b, c] = f.map(|i| mesh.vertices()[i as usize]);
+            volume += a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0]) + a[2] * (b[0] * c[1] - b[1] * c[0]);
+        }
+        assert!(volume > 0.0);
+    }
+    #[test]
+    fn test_mesh_distance_and_export() {
+        let mut mesh = generate_mesh();
+        let mut other = Array3::<bool>::from_elem([4, 5, 6], false);
+        other.slice_mut(s![0..3, 1..4, 1..5]).fill(true);
+        mesh.color_by_distance(&other);
+        let scalars = mesh.scalars().unwrap();
+        assert_eq!(scalars.len(), mesh.vertices().len());
+        assert!(scalars.iter().all(|d| d.is_finite()));
+        assert_eq!(scalars.iter().cloned().fold(0.0, f32::max), 2.0);
+
+        let stl = TempFile::new("test_mesh_distance_and_export.stl");
+        mesh.write_stl(&stl.0).unwrap();
+        assert_eq!(std::fs::metadata(&stl.0).unwrap().len(), 84 + 50 * mesh.faces().len() as u64);
+
+        let ply = TempFile::new("test_mesh_distance_and_export.ply");
+        mesh.write_ply(&ply.0).unwrap();
+        let content = std::fs::read_to_string(&ply.0).unwrap();
+        assert!(content.starts_with("ply\n"));
+        assert!(content.contains("property float distance"));
+
+        let obj = TempFile::new("test_mesh_distance_and_export.obj");
+        mesh.write_obj(&obj.0).unwrap();
+        let content = std::fs::read_to_string(&obj.0).unwrap();
+        assert_eq!(content.lines().filter(|l| l.starts_with("f ")).count(), mesh.faces().len());
+    }
+    #[test]
+    fn test_point_triangle_distance() {
+        let (a, b, c) = ([0.0, 0.0, 0.0], [0.0, 0.0, 2.0], [0.0, 2.0, 0.0]);
+        assert_eq!(point_triangle_distance(&[3.0, 0.5, 0.5], &a, &b, &c), 3.0);
+        assert_eq!(point_triangle_distance(&[0.0, -1.0, -1.0], &a, &b, &c), 2.0_f32.sqrt());
+        assert_eq!(point_triangle_distance(&[0.0, 0.0, 5.0], &a, &b, &c), 3.0);
+        assert_eq!(point_triangle_distance(&[0.0, -2.0, 1.0], &a, &b, &c), 2.0);
+    }
+    #[test]
+    fn test_mesh_distance_map() {
+        let mesh = generate_mesh();
+        let stl = TempFile::new("test_mesh_distance_map.stl");
+        mesh.write_stl(&stl.0).unwrap();
+        let read = Mesh::read_stl(&stl.0).unwrap();
+        assert_eq!(read.vertices().len(), mesh.vertices().len());
+        assert_eq!(read.faces().len(), mesh.faces().len());
+
+        let same = mesh_distance_map(&mesh, &read);
+        assert_eq!(same.hd(), 0.0);
+        assert_eq!(same.surface_dc(&0.0), 1.0);
+
+        let shifted = Mesh::from_triangles(
+            read.vertices().iter().map(|v| [v[0] + 1.0, v[1], v[2]]).collect(),
+            read.faces().to_vec(),
+        );
+        let mut map = mesh_distance_map(&read, &shifted);
+        assert!((map.hd() - 1.0).abs() < 1e-6);
+        assert!(map.assd() > 0.0);
+        assert!(map.hd_percentile(&0.5) <= 1.0);
+
+        let cloud = Mesh::from_triangles(shifted.vertices().to_vec(), Vec::new());
+        let cloud_map = mesh_distance_map(&read, &cloud);
+        assert!(cloud_map.hd() >= map.hd());
+    }
+    #[test]
+    fn test_triangle_bvh_matches_brute_force() {
+        let mut src = Array3::<bool>::from_elem([8, 9, 10], false);
+        src.slice_mut(s![1..7, 2..8, 1..9]).fill(true);
+        src.slice_mut(s![3..5, 4..6, 0..10]).fill(false);
+        let mut mesh = Mesh::new(&src, vec![1.5, 1.0, 0.8]);
+        mesh.execute();
+        let bvh = TriangleBvh::new(mesh.vertices(), mesh.faces());
+
+        let mut state = 4321_u32;
+        let mut next = || {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as f32 / 65536.0 * 14.0 - 1.0
+        };
+        for _ in 0..200 {
+            let p = [next(), next(), next()];
+            let brute = mesh.faces().iter()
+                .map(|f| {
+                    let [a, b, c] = f.map(|i| &mesh.vertices()[i as usize]);
+                    point_triangle_distance(&p, a, b, c)
+                })
+                .fold(f32::INFINITY, f32::min);
+            assert_eq!(bvh.distance(&p), brute);
+        }
+    }
+    #[test]
+    fn test_mesh_distance_map_empty() {
+        let mesh = generate_mesh();
+        let empty = Mesh::from_triangles(Vec::new(), Vec::new());
+        let mut map = mesh_distance_map(&mesh, &empty);
+        assert_eq!(map.hd(), 999999.0);
+        assert_eq!(map.hd_percentile(&0.95), 999999.0);
+        assert_eq!(mesh_distance_map(&empty, &empty).hd(), -1.0);
+        assert_eq!(mesh_distance_map(&empty, &empty).hd_percentile(&0.95), -1.0);
+    }
+}
@@ -0,0 +1,145 @@
+use ndarray::{s, Array, Array3, Ix3};
+use crate::utils::{distance_transform, label_components};
+
+/// Scaled spacing under which the ellipsoid with semi-axes `margins` (mm) becomes the unit ball.
+fn margin_spacing(zyx_spacing: &[f32], margins: &[f32; 3]) -> Vec<f32> {
+    zyx_spacing.iter().zip(margins.iter()).map(|(s, m)| s / m.max(1e-6)).collect()
+}
+
+/// Grows `arr` by an ellipsoid with semi-axes `margins` (mm, zyx), e.g. a PTV-style margin.
+pub fn expand_margin(arr: &Array<bool, Ix3>, zyx_spacing: &[f32], margins: [f32; 3]) -> Array<bool, Ix3> {
+    let distances = distance_transform(arr, &margin_spacing(zyx_spacing, &margins));
+    distances.mapv(|d| d <= 1.0)
+}
+
+/// Shrinks `arr` by an ellipsoid with semi-axes `margins` (mm, zyx). Voxels outside the
+/// volume count as background, so masks touching the border are eroded from it.
+pub fn contract_margin(arr: &Array<bool, Ix3>, zyx_spacing: &[f32], margins: [f32; 3]) -> Array<bool, Ix3> {
+    let shape = arr.shape();
+    let mut background = Array3::<bool>::from_elem((shape[0] + 2, shape[1] + 2, shape[2] + 2), true);
+    background.slice_mut(s![1..shape[0] + 1, 1..shape[1] + 1, 1..shape[2] + 1]).assign(&arr.mapv(|v| !v));
+    let distances = distance_transform(&background, &margin_spacing(zyx_spacing, &margins));
+    distances.slice(s![1..shape[0] + 1, 1..shape[1] + 1, 1..shape[2] + 1]).mapv(|d| d > 1.0)
+}
+
+/// Dilation with a ball of `radius` mm.
+pub fn dilate(arr: &Array<bool, Ix3>, zyx_spacing: &[f32], radius: f32) -> Array<bool, Ix3> {
+    expand_margin(arr, zyx_spacing, [radius; 3])
+}
+
+/// Erosion with a ball of `radius` mm.
+pub fn erode(arr: &Array<bool, Ix3>, zyx_spacing: &[f32], radius: f32) -> Array<bool, Ix3> {
+    contract_margin(arr, zyx_spacing, [radius; 3])
+}
+
+/// Erosion followed by dilation; removes structures thinner than the ball.
+pub fn opening(arr: &Array<bool, Ix3>, zyx_spacing: &[f32], radius: f32) -> Array<bool, Ix3> {
+    dilate(&erode(arr, zyx_spacing, radius), zyx_spacing, radius)
+}
+
+/// Dilation followed by erosion; closes gaps narrower than the ball. The volume is padded by
+/// the radius first so the dilation is not cut off at the border and the result stays a superset.
+pub fn closing(arr: &Array<bool, Ix3>, zyx_spacing: &[f32], radius: f32) -> Array<bool, Ix3> {
+    let shape = arr.shape();
+    let pad: Vec<usize> = zyx_spacing.iter().map(|s| (radius / s).ceil().max(0.0) as usize).collect();
+    let mut padded = Array3::<bool>::from_elem((shape[0] + 2 * pad[0], shape[1] + 2 * pad[1], shape[2] + 2 * pad[2]), false);
+    padded.slice_mut(s![pad[0]..shape[0] + pad[0], pad[1]..shape[1] + pad[1], pad[2]..shape[2] + pad[2]]).assign(arr);
+    let closed = erode(&dilate(&padded, zyx_spacing, radius), zyx_spacing, radius);
+    closed.slice(s![pad[0]..shape[0] + pad[0], pad[1]..shape[1] + pad[1], pad[2]..shape[2] + pad[2]]).to_owned()
+}
+
+/// Fills background regions that are not 6-connected to the volume border.
+pub fn fill_holes(arr: &Array<bool, Ix3>) -> Array<bool, Ix3> {
+    let shape = arr.shape();
+    let mut background = Array3::<bool>::from_elem((shape[0] + 2, shape[1] + 2, shape[2] + 2), true);
+    background.slice_mut(s![1..shape[0] + 1, 1..shape[1] + 1, 1..shape[2] + 1]).assign(&arr.mapv(|v| !v));
+    let (labels, _) = label_components(&background, 6);
+    // The padding belongs to the component that reaches the border
+    let outside = labels[[0, 0, 0]];
+    labels.slice(s![1..shape[0] + 1, 1..shape[1] + 1, 1..shape[2] + 1]).mapv(|l| l != outside)
+}
+
+/// Keeps only the largest connected component.
+pub fn largest_component(arr: &Array<bool, Ix3>, connectivity: u8) -> Array<bool, Ix3> {
+    let (labels, n_labels) = label_components(arr, connectivity);
+    let mut sizes = vec![0_u64; n_labels as usize + 1];
+    for label in labels.iter() {
+        sizes[*label as usize] += 1;
+    }
+    sizes[0] = 0;
+    let largest = (1..sizes.len()).max_by_key(|l| sizes[*l]).unwrap_or(0) as u32;
+    labels.mapv(|l| (l != 0) & (l == largest))
+}
+
+#[cfg(test)]
+mod test_morphology {
+    use ndarray::{s, Array3};
+    use crate::morphology::{closing, contract_margin, dilate, erode, expand_margin, fill_holes, largest_component, opening};
+    use crate::utils::ellipsoid_offsets;
+
+    #[test]
+    fn test_dilate_erode() {
+        let mut src = Array3::<bool>::from_elem([9, 9, 9], false);
+        src[[4, 4, 4]] = true;
+        let spacing = [3.0, 1.0, 0.5];
+
+        let dilated = dilate(&src, &spacing, 2.0);
+        assert_eq!(dilated.iter().filter(|v| **v).count(), ellipsoid_offsets(&spacing, 2.0).len());
+        assert!(erode(&dilated, &spacing, 2.0) == src);
+    }
+    #[test]
+    fn test_margins() {
+        let mut src = Array3::<bool>::from_elem([7, 7, 7], false);
+        src[[3, 3, 3]] = true;
+        let expanded = expand_margin(&src, &[1.0, 1.0, 1.0], [2.0, 0.0, 1.0]);
+
+        assert_eq!(expanded.iter().filter(|v| **v).count(), 7);
+        assert!(expanded[[1, 3, 3]] & expanded[[5, 3, 3]] & expanded[[3, 3, 2]] & !expanded[[3, 2, 3]]);
+        assert!(contract_margin(&expanded, &[1.0, 1.0, 1.0], [2.0, 0.0, 1.0]) == src);
+    }
+    #[test]
+    fn test_opening_closing() {
+        let mut src = Array3::<bool>::from_elem([7, 7, 12], false);
+        src.slice_mut(s![1..6, 1..6, 1..6]).fill(true);
+        src.slice_mut(s![3, 3, 6..11]).fill(true);
+        let mut body = Array3::<bool>::from_elem([7, 7, 12], false);
+        body.slice_mut(s![1..6, 1..6, 1..6]).fill(true);
+        // The thin protrusion does not survive the opening
+        let opened = opening(&src, &[1.0, 1.0, 1.0], 1.0);
+        assert!(opened[[3, 3, 3]] & !opened[[3, 3, 8]] & !opened[[3, 3, 10]]);
+
+        let mut gap = body.clone();
+        gap.slice_mut(s![1..6, 1..6, 3]).fill(false);
+        let closed = closing(&gap, &[1.0, 1.0, 1.0], 1.0);
+        assert!(closed.slice(s![2..5, 2..5, 3]).iter().all(|v| *v));
+        assert!(closing(&body, &[1.0, 1.0, 1.0], 1.0) == body);
+    }
+    #[test]
+    fn test_closing_at_border() {
+        // A slab on the volume border with a notch cut into it
+        let mut src = Array3::<bool>::from_elem([6, 6, 6], false);
+        src.slice_mut(s![0..3, .., ..]).fill(true);
+        src[[0, 2, 2]] = false;
+        let closed = closing(&src, &[1.0, 1.0, 1.0], 1.5);
+
+        assert!(src.iter().zip(closed.iter()).all(|(a, b)| !*a | *b));
+        assert!(closed[[0, 0, 0]] & closed[[0, 5, 5]] & closed[[2, 5, 0]]);
+        assert!(closed[[0, 2, 2]]);
+    }
+    #[test]
+    fn test_fill_holes_and_largest_component() {
+        let mut src = Array3::<bool>::from_elem([6, 6, 8], false);
+        src.slice_mut(s![0..5, 0..5, 0..5]).fill(true);
+        src[[2, 2, 2]] = false;
+        src[[0, 0, 7]] = true;
+
+        let filled = fill_holes(&src);
+        assert!(filled[[2, 2, 2]] & !filled[[5, 5, 7]]);
+        assert_eq!(filled.iter().filter(|v| **v).count(), 126);
+
+        let largest = largest_component(&src, 26);
+        assert!(!largest[[0, 0, 7]]);
+        assert_eq!(largest.iter().filter(|v| **v).count(), 124);
+        assert!(largest_component(&Array3::<bool>::from_elem([2, 2, 2], false), 26).iter().all(|v| !*v));
+    }
+}
@@ -1,64 +1,266 @@
+use std::collections::BTreeMap;
 use ndarray::prelude::*;
+use crate::bitmask::BitMask;
+use crate::utils::slice_mask;
+
+/// Voxel count as stored in a confusion matrix; counts beyond u32 are not supported.
+fn voxel_count(n: u64) -> u32 {
+    u32::try_from(n).expect("voxel count does not fit in u32")
+}
 
 pub struct ConfusionMatrix {
-    ref_arr: Array<bool, Ix3>,
-    other_arr: Array<bool, Ix3>,
-    tpos: u16,
-    tneg: u16,
-    fpos: u16,
-    fneg: u16,
+    ref_mask: BitMask,
+    other_mask: BitMask,
+    tpos: u32,
+    tneg: u32,
+    fpos: u32,
+    fneg: u32,
+    roi: Option<BitMask>,
 }
 
 impl ConfusionMatrix {
     pub fn new(ref_arr: &Array<bool, Ix3>, other_arr: &Array<bool, Ix3>) -> ConfusionMatrix{
+        ConfusionMatrix::from_masks(BitMask::from_array(ref_arr), BitMask::from_array(other_arr))
+    }
+    /// Confusion matrix of two bit-packed masks, e.g. packed straight from a borrowed view.
+    pub fn from_masks(ref_mask: BitMask, other_mask: BitMask) -> ConfusionMatrix{
         ConfusionMatrix{
-            ref_arr: ref_arr.clone(),
-            other_arr: other_arr.clone(),
+            ref_mask,
+            other_mask,
             tpos: 0,
             tneg: 0,
             fpos: 0,
             fneg: 0,
+            roi: None,
         }
     }
-    pub fn execute(&mut self){
-        let mut ref_val: &bool;
-        let mut other_val: &bool;
-
-        for z in 0..self.ref_arr.shape()[0] {
-            for y in 0..self.ref_arr.shape()[1] {
-                for x in 0..self.ref_arr.shape()[2] {
-                    ref_val = self.ref_arr.get([z, y, x]).unwrap();
-                    other_val = self.other_arr.get([z, y, x]).unwrap();
-                    if ref_val & ref_val.eq(other_val)  { self.tpos += 1; }  // True positive
-                    else if !ref_val & ref_val.eq(other_val) { self.tneg += 1; } // true negative
-                    else if !ref_val & ref_val.ne(other_val) { self.fpos += 1; } // false positive
-                    else if ref_val & ref_val.ne(other_val) { self.fneg += 1; } // false negative
-                    }
-                }
-            }
+    /// Confusion matrix from precomputed voxel counts, e.g. for a pair of matched instances.
+    pub fn from_counts(tpos: u32, tneg: u32, fpos: u32, fneg: u32) -> ConfusionMatrix {
+        ConfusionMatrix{
+            ref_mask: BitMask::new([0, 0, 0]),
+            other_mask: BitMask::new([0, 0, 0]),
+            tpos,
+            tneg,
+            fpos,
+            fneg,
+            roi: None,
         }
+    }
+    /// Only count voxels inside `roi`; everything outside is ignored.
+    pub fn with_roi(self, roi: &Array<bool, Ix3>) -> ConfusionMatrix {
+        self.with_roi_mask(BitMask::from_array(roi))
+    }
+    /// Like `with_roi`; an ROI that is already set is intersected with `roi`.
+    pub fn with_roi_mask(mut self, roi: BitMask) -> ConfusionMatrix {
+        self.roi = Some(match self.roi {
+            Some(current) => current.and(&roi),
+            None => roi,
+        });
+        self
+    }
+    /// Exclude the voxels of `ignore`, e.g. ambiguous or unlabelled regions.
+    pub fn with_ignore(self, ignore: &Array<bool, Ix3>) -> ConfusionMatrix {
+        self.with_roi(&ignore.mapv(|v| !v))
+    }
+    /// Only count voxels on the listed slices along `axis`, e.g. from `reference_slices`.
+    pub fn with_slices(self, slices: &[usize], axis: usize) -> ConfusionMatrix {
+        let shape = self.ref_mask.shape();
+        self.with_roi(&slice_mask(shape, slices, axis))
+    }
+    /// Counts whole 64-voxel words at a time with popcount; true negatives are the
+    /// remaining voxels (inside the ROI).
+    pub fn execute(&mut self){
+        let roi = self.roi.as_ref();
+        let n_voxels = match roi {
+            Some(roi) => voxel_count(roi.count()),
+            None => voxel_count(self.ref_mask.len() as u64),
+        };
+        self.tpos = voxel_count(self.ref_mask.count_combined(&self.other_mask, roi, |r, o| r & o));
+        self.fpos = voxel_count(self.ref_mask.count_combined(&self.other_mask, roi, |r, o| !r & o));
+        self.fneg = voxel_count(self.ref_mask.count_combined(&self.other_mask, roi, |r, o| r & !o));
+        self.tneg = n_voxels - self.tpos - self.fpos - self.fneg;
+    }
     pub fn dc(&self) -> f32 {
-        2.0*f32::from(self.tpos)/f32::from(&self.fpos + &self.fneg + 2 * self.tpos)
+        2.0*(self.tpos as f32)/((self.fpos as u64 + self.fneg as u64 + 2 * self.tpos as u64) as f32)
         }
     pub fn jc(&self) -> f32 {
-        f32::from(self.tpos)/f32::from(self.tpos + self.fneg + self.fpos)
+        (self.tpos as f32)/((self.tpos as u64 + self.fneg as u64 + self.fpos as u64) as f32)
+        }
+    pub fn tpos(&self) -> u32 {
+        self.tpos
+    }
+    pub fn tneg(&self) -> u32 {
+        self.tneg
+    }
+    pub fn fpos(&self) -> u32 {
+        self.fpos
+    }
+    pub fn fneg(&self) -> u32 {
+        self.fneg
+    }
+    /// Number of foreground voxels in the reference.
+    pub fn ref_count(&self) -> u32 {
+        self.tpos + self.fneg
+    }
+    /// Number of foreground voxels in the other mask.
+    pub fn other_count(&self) -> u32 {
+        self.tpos + self.fpos
+    }
+}
+
+/// Per-label weights for the generalised Dice score, based on the reference volume of each label.
+pub enum LabelWeighting {
+    Uniform,
+    InverseVolume,
+    InverseSquaredVolume,
+}
+
+/// One-vs-rest confusion matrices for every label of two integer label maps,
+/// with macro/micro averaged and generalised (volume-weighted) aggregates.
+pub struct MultiClassConfusionMatrix {
+    ref_arr: Array<u32, Ix3>,
+    other_arr: Array<u32, Ix3>,
+    include_background: bool,
+    labels: Vec<u32>,
+    cms: Vec<ConfusionMatrix>,
+}
+
+impl MultiClassConfusionMatrix {
+    pub fn new(ref_arr: &Array<u32, Ix3>, other_arr: &Array<u32, Ix3>, include_background: bool) -> MultiClassConfusionMatrix {
+        assert_eq!(ref_arr.shape(), other_arr.shape(), "label maps must have the same shape");
+        MultiClassConfusionMatrix {
+            ref_arr: ref_arr.clone(),
+            other_arr: other_arr.clone(),
+            include_background,
+            labels: Vec::new(),
+            cms: Vec::new(),
+        }
+    }
+    pub fn execute(&mut self) {
+        let mut counts = BTreeMap::<u32, [u32; 3]>::new();  // label -> [tpos, fpos, fneg]
+        for (ref_label, other_label) in self.ref_arr.iter().zip(self.other_arr.iter()) {
+            if ref_label == other_label {
+                counts.entry(*ref_label).or_insert([0; 3])[0] += 1;
+            } else {
+                counts.entry(*other_label).or_insert([0; 3])[1] += 1;
+                counts.entry(*ref_label).or_insert([0; 3])[2] += 1;
+            }
+        }
+        let n_voxels = voxel_count(self.ref_arr.len() as u64);
+        for (label, [tpos, fpos, fneg]) in counts {
+            if (label == 0) & !self.include_background {
+                continue;
+            }
+            self.labels.push(label);
+            self.cms.push(ConfusionMatrix::from_counts(tpos, n_voxels - tpos - fpos - fneg, fpos, fneg));
+        }
+    }
+    pub fn labels(&self) -> &[u32] {
+        &self.labels
+    }
+    pub fn dc_per_label(&self) -> Vec<f32> {
+        self.cms.iter().map(|cm| cm.dc()).collect()
+    }
+    pub fn jc_per_label(&self) -> Vec<f32> {
+        self.cms.iter().map(|cm| cm.jc()).collect()
+    }
+    pub fn macro_dc(&self) -> f32 {
+        self.dc_per_label().iter().sum::<f32>() / self.cms.len() as f32
+    }
+    pub fn macro_jc(&self) -> f32 {
+        self.jc_per_label().iter().sum::<f32>() / self.cms.len() as f32
+    }
+    /// Summed tpos, fpos and fneg over labels. Each voxel counts towards these at most
+    /// once, so the sums cannot overflow; tneg is not pooled and stays 0.
+    fn pooled(&self) -> ConfusionMatrix {
+        let mut pooled = ConfusionMatrix::from_counts(0, 0, 0, 0);
+        for cm in &self.cms {
+            pooled.tpos += cm.tpos;
+            pooled.fpos += cm.fpos;
+            pooled.fneg += cm.fneg;
         }
+        pooled
+    }
+    pub fn micro_dc(&self) -> f32 {
+        self.pooled().dc()
+    }
+    pub fn micro_jc(&self) -> f32 {
+        self.pooled().jc()
+    }
+    /// Generalised Dice score (Sudre et al., 2017). Labels absent from the reference get
+    /// the largest finite weight instead of an infinite one.
+    pub fn generalised_dc(&self, weighting: &LabelWeighting) -> f32 {
+        let mut weights: Vec<f64> = self.cms.iter()
+            .map(|cm| {
+                let volume = (cm.tpos + cm.fneg) as f64;
+                match weighting {
+                    LabelWeighting::Uniform => 1.0,
+                    LabelWeighting::InverseVolume => 1.0 / volume,
+                    LabelWeighting::InverseSquaredVolume => 1.0 / volume.powi(2),
+                }
+            })
+            .collect();
+        let max_weight = weights.iter().cloned().filter(|w| w.is_finite()).fold(0.0, f64::max);
+        for w in weights.iter_mut() {
+            if w.is_infinite() {
+                *w = max_weight;
+            }
+        }
+        let mut numerator = 0.0;
+        let mut denominator = 0.0;
+        for (cm, w) in self.cms.iter().zip(weights.iter()) {
+            numerator += w * cm.tpos as f64;
+            denominator += w * (2 * cm.tpos + cm.fpos + cm.fneg) as f64;
+        }
+        (2.0 * numerator / denominator) as f32
+    }
 }
 
 
 
 #[cfg(test)]
 mod test_overlap {
-    use ndarray::Array3;
-    use crate::overlap::ConfusionMatrix;
+    use ndarray::{s, Array3};
+    use crate::overlap::{ConfusionMatrix, LabelWeighting, MultiClassConfusionMatrix};
 
+    #[test]
+    fn test_confusion_matrix_small_structure() {
+        let mut src = Array3::<bool>::from_elem([20, 20, 20], false);
+        let mut dst = Array3::<bool>::from_elem([20, 20, 20], false);
+        src.slice_mut(s![5..7, 5..7, 5..7]).fill(true);
+        dst.slice_mut(s![6..8, 5..7, 5..7]).fill(true);
+        let mut cm = ConfusionMatrix::new(&src, &dst);
+        cm.execute();
+        assert_eq!((cm.tpos, cm.fpos, cm.fneg, cm.tneg), (4, 4, 4, 8000 - 12));
+
+        let mut roi = Array3::<bool>::from_elem([20, 20, 20], false);
+        roi.slice_mut(s![0..7, .., ..]).fill(true);
+        let mut cm = ConfusionMatrix::new(&src, &dst).with_roi(&roi);
+        cm.execute();
+        assert_eq!((cm.tpos, cm.fpos, cm.fneg, cm.tneg), (4, 0, 4, 2800 - 8));
+
+        let mut cm = ConfusionMatrix::new(&src, &dst).with_ignore(&roi.mapv(|v| !v));
+        cm.execute();
+        assert_eq!((cm.tpos, cm.fpos, cm.fneg, cm.tneg), (4, 0, 4, 2800 - 8));
+        let mut cm = ConfusionMatrix::new(&src, &dst).with_slices(&[0, 1, 2, 3, 4, 5, 6], 0);
+        cm.execute();
+        assert_eq!((cm.tpos, cm.fpos, cm.fneg, cm.tneg), (4, 0, 4, 2800 - 8));
+        let mut cm = ConfusionMatrix::new(&src, &dst).with_roi(&roi).with_slices(&[6, 7], 0);
+        cm.execute();
+        assert_eq!((cm.tpos, cm.fpos, cm.fneg, cm.tneg), (4, 0, 0, 400 - 4));
+
+        let empty = Array3::<bool>::from_elem([20, 20, 20], false);
+        let mut cm = ConfusionMatrix::new(&empty, &empty);
+        cm.execute();
+        assert_eq!(cm.tneg, 8000);
+    }
     #[test]
     fn test_dc() {
         let src = Array3::<u8>::ones([10, 10, 10]);
         let dst = Array3::<u8>::zeros([10, 10, 10]);
 
-        let src = src.mapv(|x| (x != 0));
-        let mut dst = dst.mapv(|x| (x != 0));
+        let src = src.mapv(|x| x != 0);
+        let mut dst = dst.mapv(|x| x != 0);
 
         for z in 0..5 {
             for y in 0..10 {
@@ -110,8 +312,8 @@ mod test_overlap {
         let src = Array3::<u8>::ones([10, 10, 10]);
         let dst = Array3::<u8>::zeros([10, 10, 10]);
 
-        let src = src.mapv(|x| (x != 0));
-        let mut dst = dst.mapv(|x| (x != 0));
+        let src = src.mapv(|x| x != 0);
+        let mut dst = dst.mapv(|x| x != 0);
 
         for z in 0..5 {
             for y in 0..10 {
@@ -158,4 +360,53 @@ mod test_overlap {
         let jacc = cm.jc();
         assert_eq!(jacc, 0.0);
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_roi() {
+        let src = Array3::<bool>::from_elem([10, 10, 10], true);
+        let mut dst = Array3::<bool>::from_elem([10, 10, 10], false);
+        let mut roi = Array3::<bool>::from_elem([10, 10, 10], false);
+        for z in 0..5 {
+            for y in 0..10 {
+                for x in 0..10 {
+                    dst[[z, y, x]] = true;
+                    roi[[z, y, x]] = true;
+                }
+            }
+        }
+        let mut cm = ConfusionMatrix::new(&src, &dst).with_roi(&roi);
+        cm.execute();
+        assert_eq!(cm.dc(), 1.0);
+        assert_eq!(cm.jc(), 1.0);
+    }
+    #[test]
+    fn test_multiclass() {
+        let src = Array3::<u32>::from(vec![
+               [[1, 1, 1, 1, 0],
+                [2, 2, 0, 0, 0]]]);
+        let dst = Array3::<u32>::from(vec![
+               [[1, 1, 1, 0, 0],
+                [2, 2, 2, 2, 3]]]);
+
+        let mut cm = MultiClassConfusionMatrix::new(&src, &dst, false);
+        cm.execute();
+
+        assert_eq!(cm.labels(), &[1, 2, 3]);
+        assert_eq!(cm.dc_per_label(), vec![6.0 / 7.0, 4.0 / 6.0, 0.0]);
+        assert_eq!(cm.jc_per_label(), vec![0.75, 0.5, 0.0]);
+        assert_eq!(cm.macro_dc(), (6.0 / 7.0 + 4.0 / 6.0) / 3.0);
+        assert_eq!(cm.micro_dc(), 10.0 / 14.0);
+        assert_eq!(cm.micro_jc(), 5.0 / 9.0);
+        assert_eq!(cm.generalised_dc(&LabelWeighting::Uniform), cm.micro_dc());
+        // Label 3 is absent from the reference and gets the weight of label 2
+        assert_eq!(cm.generalised_dc(&LabelWeighting::InverseVolume), (2.0 * (3.0 / 4.0 + 2.0 / 2.0)) / (7.0 / 4.0 + 6.0 / 2.0 + 1.0 / 2.0));
+
+        let mut cm = MultiClassConfusionMatrix::new(&src, &dst, true);
+        cm.execute();
+        assert_eq!(cm.labels(), &[0, 1, 2, 3]);
+    }
+    #[test]
+    #[should_panic(expected = "label maps must have the same shape")]
+    fn test_multiclass_shape_mismatch() {
+        MultiClassConfusionMatrix::new(&Array3::<u32>::zeros([2, 3, 4]), &Array3::<u32>::zeros([2, 3, 5]), false);
+    }
+}
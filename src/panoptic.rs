@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use ndarray::{Array, Ix3};
+use crate::overlap::ConfusionMatrix;
+
+/// Panoptic Quality between two instance label maps (0 is background). Instances are
+/// matched when their IoU exceeds 0.5, which makes the matching unique.
+pub struct PanopticQuality {
+    ref_arr: Array<u32, Ix3>,
+    other_arr: Array<u32, Ix3>,
+    tpos: u32,
+    fpos: u32,
+    fneg: u32,
+    iou_sum: f32,
+}
+
+impl PanopticQuality {
+    pub fn new(ref_arr: &Array<u32, Ix3>, other_arr: &Array<u32, Ix3>) -> PanopticQuality {
+        assert_eq!(ref_arr.shape(), other_arr.shape(), "label maps must have the same shape");
+        PanopticQuality {
+            ref_arr: ref_arr.clone(),
+            other_arr: other_arr.clone(),
+            tpos: 0,
+            fpos: 0,
+            fneg: 0,
+            iou_sum: 0.0,
+        }
+    }
+    pub fn execute(&mut self) {
+        let mut ref_sizes = HashMap::<u32, u32>::new();
+        let mut other_sizes = HashMap::<u32, u32>::new();
+        let mut intersections = HashMap::<(u32, u32), u32>::new();
+        for (ref_label, other_label) in self.ref_arr.iter().zip(self.other_arr.iter()) {
+            if *ref_label != 0 {
+                *ref_sizes.entry(*ref_label).or_insert(0) += 1;
+            }
+            if *other_label != 0 {
+                *other_sizes.entry(*other_label).or_insert(0) += 1;
+            }
+            if (*ref_label != 0) & (*other_label != 0) {
+                *intersections.entry((*ref_label, *other_label)).or_insert(0) += 1;
+            }
+        }
+
+        for ((ref_label, other_label), intersection) in &intersections {
+            let ref_size = ref_sizes[ref_label];
+            let other_size = other_sizes[other_label];
+            let cm = ConfusionMatrix::from_counts(
+                *intersection,
+                0,
+                other_size - intersection,
+                ref_size - intersection,
+            );
+            let iou = cm.jc();
+            if iou > 0.5 {
+                self.tpos += 1;
+                self.iou_sum += iou;
+            }
+        }
+        self.fpos = other_sizes.len() as u32 - self.tpos;
+        self.fneg = ref_sizes.len() as u32 - self.tpos;
+    }
+    pub fn tpos(&self) -> u32 {
+        self.tpos
+    }
+    pub fn fpos(&self) -> u32 {
+        self.fpos
+    }
+    pub fn fneg(&self) -> u32 {
+        self.fneg
+    }
+    /// Segmentation Quality: mean IoU of the matched instances.
+    pub fn sq(&self) -> f32 {
+        self.iou_sum / self.tpos as f32
+    }
+    /// Recognition Quality: F1 score of the instance matching.
+    pub fn rq(&self) -> f32 {
+        self.tpos as f32 / (self.tpos as f32 + 0.5 * self.fpos as f32 + 0.5 * self.fneg as f32)
+    }
+    pub fn pq(&self) -> f32 {
+        self.iou_sum / (self.tpos as f32 + 0.5 * self.fpos as f32 + 0.5 * self.fneg as f32)
+    }
+}
+
+#[cfg(test)]
+mod test_panoptic {
+    use ndarray::Array3;
+    use crate::panoptic::PanopticQuality;
+
+    #[test]
+    fn test_panoptic_quality() {
+        let src = Array3::<u32>::from(vec![
+               [[1, 1, 1, 1, 0],
+                [2, 2, 2, 2, 0],
+                [0, 0, 0, 0, 0],
+                [3, 3, 0, 0, 0]]]);
+        let dst = Array3::<u32>::from(vec![
+               [[5, 5, 5, 5, 0],
+                [7, 7, 7, 0, 0],
+                [0, 0, 0, 0, 9],
+                [0, 0, 0, 0, 0]]]);
+
+        let mut pq = PanopticQuality::new(&src, &dst);
+        pq.execute();
+
+        assert_eq!((pq.tpos(), pq.fpos(), pq.fneg()), (2, 1, 1));
+        assert_eq!(pq.sq(), 0.875);
+        assert_eq!(pq.rq(), 2.0 / 3.0);
+        assert_eq!(pq.pq(), 1.75 / 3.0);
+    }
+    #[test]
+    #[should_panic(expected = "label maps must have the same shape")]
+    fn test_shape_mismatch() {
+        PanopticQuality::new(&Array3::<u32>::zeros([2, 3, 4]), &Array3::<u32>::zeros([2, 4, 4]));
+    }
+}
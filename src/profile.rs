@@ -0,0 +1,171 @@
+use ndarray::{s, Array, Axis, Ix3};
+use crate::apl::APL;
+use crate::distance::{HausdorffMapDirected, HausdorffMapUndirected};
+use crate::overlap::ConfusionMatrix;
+
+/// Per-slice 2D metrics along one axis of the volume (0 = z/axial, 1 = y, 2 = x).
+/// Every metric is a vector of length n_slices. Slices where a metric is undefined
+/// (e.g. Dice with both slices empty, or HD with one side empty) hold NaN.
+pub struct SliceProfile {
+    ref_arr: Array<bool, Ix3>,
+    other_arr: Array<bool, Ix3>,
+    zyx_spacing: Vec<f32>,
+    tolerance: f32,
+    dcs: Vec<f32>,
+    jcs: Vec<f32>,
+    hds: Vec<f32>,
+    surface_dcs: Vec<f32>,
+    apls: Vec<u32>,
+}
+
+/// Axis order that moves `axis` to the front while keeping the other two in order.
+fn axis_order(axis: usize) -> [usize; 3] {
+    match axis {
+        0 => [0, 1, 2],
+        1 => [1, 0, 2],
+        2 => [2, 0, 1],
+        _ => panic!("axis must be 0, 1 or 2, got {}", axis),
+    }
+}
+
+impl SliceProfile {
+    pub fn new(ref_arr: &Array<bool, Ix3>,
+               other_arr: &Array<bool, Ix3>,
+               zyx_spacing: Vec<f32>,
+               axis: usize,
+               tolerance: f32) -> SliceProfile {
+        let order = axis_order(axis);
+        SliceProfile {
+            ref_arr: ref_arr.view().permuted_axes(order).to_owned(),
+            other_arr: other_arr.view().permuted_axes(order).to_owned(),
+            zyx_spacing: order.iter().map(|i| zyx_spacing[*i]).collect(),
+            tolerance,
+            dcs: Vec::new(),
+            jcs: Vec::new(),
+            hds: Vec::new(),
+            surface_dcs: Vec::new(),
+            apls: Vec::new(),
+        }
+    }
+    pub fn execute(&mut self) {
+        for i in 0..self.ref_arr.len_of(Axis(0)) {
+            let ref_slice = self.ref_arr.slice(s![i..i + 1, .., ..]).to_owned();
+            let other_slice = self.other_arr.slice(s![i..i + 1, .., ..]).to_owned();
+
+            let mut cm = ConfusionMatrix::new(&ref_slice, &other_slice);
+            cm.execute();
+            self.dcs.push(cm.dc());
+            self.jcs.push(cm.jc());
+
+            let mut apl = APL::new(&ref_slice, &other_slice);
+            apl.execute();
+            self.apls.push(apl.apl());
+
+            if ref_slice.iter().any(|v| *v) & other_slice.iter().any(|v| *v) {
+                let mut ref_to_other = HausdorffMapDirected::new_2d(&ref_slice, &other_slice, self.zyx_spacing.clone());
+                let mut other_to_ref = HausdorffMapDirected::new_2d(&other_slice, &ref_slice, self.zyx_spacing.clone());
+                ref_to_other.execute();
+                other_to_ref.execute();
+                self.hds.push(ref_to_other.hd().max(other_to_ref.hd()));
+                // Symmetric like the HD: fraction of both contours within tolerance
+                let hd_map = HausdorffMapUndirected::from_distances(ref_to_other.distances().to_vec(), other_to_ref.distances().to_vec());
+                self.surface_dcs.push(hd_map.surface_dc(&self.tolerance));
+            } else {
+                self.hds.push(f32::NAN);
+                self.surface_dcs.push(f32::NAN);
+            }
+        }
+    }
+    pub fn n_slices(&self) -> usize {
+        self.ref_arr.len_of(Axis(0))
+    }
+    pub fn dc(&self) -> &[f32] {
+        &self.dcs
+    }
+    pub fn jc(&self) -> &[f32] {
+        &self.jcs
+    }
+    pub fn hd(&self) -> &[f32] {
+        &self.hds
+    }
+    pub fn surface_dc(&self) -> &[f32] {
+        &self.surface_dcs
+    }
+    pub fn apl(&self) -> &[u32] {
+        &self.apls
+    }
+}
+
+#[cfg(test)]
+mod test_profile {
+    use ndarray::Array3;
+    use crate::profile::SliceProfile;
+
+    #[test]
+    fn test_slice_profile_axial() {
+        let mut src = Array3::<bool>::from_elem([4, 6, 6], false);
+        let mut dst = Array3::<bool>::from_elem([4, 6, 6], false);
+        for z in 0..4 {
+            for y in 1..5 {
+                for x in 1..5 {
+                    src[[z, y, x]] = true;
+                    if z < 2 {
+                        dst[[z, y, x]] = true;
+                    }
+                }
+            }
+        }
+        let mut profile = SliceProfile::new(&src, &dst, vec![3.0, 1.0, 1.0], 0, 1.0);
+        profile.execute();
+
+        assert_eq!(profile.n_slices(), 4);
+        assert_eq!(profile.dc(), &[1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(profile.jc(), &[1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(profile.apl(), &[0, 0, 0, 0]);
+        assert_eq!(&profile.hd()[..2], &[0.0, 0.0]);
+        assert!(profile.hd()[2].is_nan() & profile.hd()[3].is_nan());
+        assert_eq!(&profile.surface_dc()[..2], &[1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_slice_profile_other_axis() {
+        let mut src = Array3::<bool>::from_elem([4, 6, 6], false);
+        let mut dst = Array3::<bool>::from_elem([4, 6, 6], false);
+        for z in 0..4 {
+            for y in 1..5 {
+                for x in 1..5 {
+                    src[[z, y, x]] = true;
+                    dst[[z, y, x]] = x < 4;
+                }
+            }
+        }
+        let mut profile = SliceProfile::new(&src, &dst, vec![3.0, 2.0, 1.0], 2, 1.0);
+        profile.execute();
+
+        assert_eq!(profile.n_slices(), 6);
+        assert!(profile.dc()[0].is_nan() & profile.dc()[5].is_nan());
+        assert_eq!(&profile.dc()[1..5], &[1.0, 1.0, 1.0, 0.0]);
+        assert_eq!(profile.apl()[4], 0);
+        assert!(profile.hd()[4].is_nan());
+    }
+    #[test]
+    fn test_slice_profile_surface_dc_is_symmetric() {
+        // 4x4 square against a centred 2x2 square: 8 of the 12 reference contour voxels
+        // and all 4 other contour voxels are within 1 mm of the other contour
+        let mut src = Array3::<bool>::from_elem([1, 6, 6], false);
+        let mut dst = Array3::<bool>::from_elem([1, 6, 6], false);
+        for y in 1..5 {
+            for x in 1..5 {
+                src[[0, y, x]] = true;
+                dst[[0, y, x]] = (2..4).contains(&y) & (2..4).contains(&x);
+            }
+        }
+        let mut profile = SliceProfile::new(&src, &dst, vec![1.0, 1.0, 1.0], 0, 1.0);
+        profile.execute();
+        assert_eq!(profile.surface_dc(), &[0.75]);
+
+        let mut profile = SliceProfile::new(&dst, &src, vec![1.0, 1.0, 1.0], 0, 1.0);
+        profile.execute();
+        assert_eq!(profile.surface_dc(), &[0.75]);
+    }
+}
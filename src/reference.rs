@@ -0,0 +1,134 @@
+use ndarray::{Array, Ix3};
+use crate::bitmask::BitMask;
+use crate::distance::HausdorffMapUndirected;
+use crate::kdtree::KdTree;
+use crate::overlap::ConfusionMatrix;
+use crate::utils::{bounding_box, distance_transform, generate_edge};
+use crate::volume::voxel_volume;
+
+/// Reference structure prepared once for comparison against many predictions: its packed
+/// mask, surface points, the distance transform of its surface, volume and bounding box.
+/// Distances from a prediction's surface to the reference are looked up in the distance
+/// transform, so they match `HausdorffMapDirected` up to floating point rounding.
+pub struct ReferenceSurface {
+    mask: BitMask,
+    zyx_spacing: Vec<f32>,
+    edge_points: Vec<[usize; 3]>,
+    distance_map: Array<f32, Ix3>,
+    bbox: Option<[[usize; 2]; 3]>,
+}
+
+fn physical_points(points: &[[usize; 3]], zyx_spacing: &[f32]) -> Vec<[f32; 3]> {
+    points.iter()
+        .map(|p| [p[0] as f32 * zyx_spacing[0], p[1] as f32 * zyx_spacing[1], p[2] as f32 * zyx_spacing[2]])
+        .collect()
+}
+
+fn edge_points(edge: &Array<bool, Ix3>) -> Vec<[usize; 3]> {
+    edge.indexed_iter()
+        .filter(|(_, v)| **v)
+        .map(|((z, y, x), _)| [z, y, x])
+        .collect()
+}
+
+impl ReferenceSurface {
+    pub fn new(ref_arr: &Array<bool, Ix3>, zyx_spacing: Vec<f32>) -> ReferenceSurface {
+        let edge = generate_edge(ref_arr, false);
+        ReferenceSurface {
+            mask: BitMask::from_array(ref_arr),
+            distance_map: distance_transform(&edge, &zyx_spacing),
+            edge_points: edge_points(&edge),
+            bbox: bounding_box(ref_arr),
+            zyx_spacing,
+        }
+    }
+    /// Volume in ml.
+    pub fn volume(&self) -> f32 {
+        (self.mask.count() as f64 * voxel_volume(&self.zyx_spacing) as f64 / 1000.0) as f32
+    }
+    pub fn zyx_spacing(&self) -> &[f32] {
+        &self.zyx_spacing
+    }
+    /// Inclusive [min, max] index along each axis, or None if the reference is empty.
+    pub fn bounding_box(&self) -> Option<[[usize; 2]; 3]> {
+        self.bbox
+    }
+    pub fn edge_points(&self) -> &[[usize; 3]] {
+        &self.edge_points
+    }
+    /// Distance (mm) from every voxel to the reference surface.
+    pub fn distance_map(&self) -> &Array<f32, Ix3> {
+        &self.distance_map
+    }
+    /// Executed confusion matrix of the reference and `other_arr`, or None if the shapes differ.
+    pub fn confusion_matrix(&self, other_arr: &Array<bool, Ix3>) -> Option<ConfusionMatrix> {
+        if other_arr.shape() != self.distance_map.shape() {
+            return None;
+        }
+        let other = BitMask::from_array(other_arr);
+        let tpos = self.mask.count_combined(&other, None, |r, o| r & o) as u32;
+        let fpos = self.mask.count_combined(&other, None, |r, o| !r & o) as u32;
+        let fneg = self.mask.count_combined(&other, None, |r, o| r & !o) as u32;
+        let tneg = self.mask.len() as u32 - tpos - fpos - fneg;
+        Some(ConfusionMatrix::from_counts(tpos, tneg, fpos, fneg))
+    }
+    /// Surface distances between the reference and `other_arr`, with the same conventions
+    /// as `HausdorffMapUndirected` (999999 for points facing an empty surface), or None if
+    /// the shapes differ.
+    pub fn hausdorff_map(&self, other_arr: &Array<bool, Ix3>) -> Option<HausdorffMapUndirected> {
+        if other_arr.shape() != self.distance_map.shape() {
+            return None;
+        }
+        let other_points = edge_points(&generate_edge(other_arr, false));
+        let other_to_ref = other_points.iter()
+            .map(|p| {
+                let d = self.distance_map[*p];
+                if d.is_finite() { d } else { 999999.0 }
+            })
+            .collect();
+        let tree = KdTree::new(physical_points(&other_points, &self.zyx_spacing));
+        let ref_to_other = physical_points(&self.edge_points, &self.zyx_spacing).iter()
+            .map(|p| tree.nearest(p).map_or(999999.0, |(_, d)| d))
+            .collect();
+        Some(HausdorffMapUndirected::from_distances(ref_to_other, other_to_ref))
+    }
+}
+
+#[cfg(test)]
+mod test_reference {
+    use ndarray::{s, Array3};
+    use crate::distance::{DistanceBackend, HausdorffMapUndirected};
+    use crate::overlap::ConfusionMatrix;
+    use crate::reference::ReferenceSurface;
+
+    #[test]
+    fn test_reference_surface_matches_pairwise() {
+        let spacing = vec![2.5, 0.7, 1.3];
+        let mut src = Array3::<bool>::from_elem([8, 10, 12], false);
+        src.slice_mut(s![2..6, 2..8, 3..10]).fill(true);
+        let reference = ReferenceSurface::new(&src, spacing.clone());
+        assert_eq!(reference.bounding_box(), Some([[2, 5], [2, 7], [3, 9]]));
+        assert!((reference.volume() - 168.0 * 2.5 * 0.7 * 1.3 / 1000.0).abs() < 1e-6);
+
+        for shift in 0..3 {
+            let mut dst = Array3::<bool>::from_elem([8, 10, 12], false);
+            dst.slice_mut(s![2 + shift..7, 1..8, 3 + shift..9]).fill(true);
+
+            let cm = reference.confusion_matrix(&dst).unwrap();
+            let mut dense = ConfusionMatrix::new(&src, &dst);
+            dense.execute();
+            assert_eq!((cm.tpos(), cm.tneg(), cm.fpos(), cm.fneg()), (dense.tpos(), dense.tneg(), dense.fpos(), dense.fneg()));
+
+            let mut map = reference.hausdorff_map(&dst).unwrap();
+            let mut pairwise = HausdorffMapUndirected::new(&src, &dst, spacing.clone()).with_backend(DistanceBackend::KdTree);
+            pairwise.execute();
+            assert!((map.hd() - pairwise.hd()).abs() < 1e-5);
+            assert!((map.assd() - pairwise.assd()).abs() < 1e-5);
+            assert!((map.hd_percentile(&0.9) - pairwise.hd_percentile(&0.9)).abs() < 1e-5);
+            assert_eq!(map.surface_dc(&1.0), pairwise.surface_dc(&1.0));
+        }
+        let wrong_shape = Array3::<bool>::from_elem([8, 10, 11], false);
+        assert!(reference.confusion_matrix(&wrong_shape).is_none());
+        assert!(reference.hausdorff_map(&wrong_shape).is_none());
+    }
+}
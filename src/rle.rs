@@ -0,0 +1,327 @@
+use std::collections::BTreeMap;
+use ndarray::{Array, Ix3};
+use crate::apl::APL;
+use crate::overlap::ConfusionMatrix;
+
+/// Run-length encoded 3D mask. Voxels are visited in C order (z, then y, then x) and
+/// `counts` holds alternating run lengths, starting with a (possibly empty) background run.
+/// Metrics on two masks work on the runs without decoding either.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RleMask {
+    shape: [usize; 3],
+    counts: Vec<u32>,
+}
+
+type Intervals = Vec<(usize, usize)>;
+
+/// Voxel order within a y-x slice for per-slice RLE. COCO (pycocotools) encodes
+/// images in Fortran order, i.e. column by column (x, then y).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SliceOrder {
+    C,
+    Fortran,
+}
+
+/// Alternating run lengths of a sequence of voxels, starting with a background run.
+fn encode_runs(values: impl Iterator<Item = bool>) -> Vec<u32> {
+    let mut counts = vec![0_u32];
+    let mut current = false;
+    for val in values {
+        if val != current {
+            counts.push(0);
+            current = val;
+        }
+        *counts.last_mut().unwrap() += 1;
+    }
+    counts
+}
+
+/// Re-encode the runs of a row-major `rows` x `cols` grid in column-major order.
+fn transpose_runs(counts: &[u32], rows: usize, cols: usize) -> Vec<u32> {
+    let mut flat = Vec::with_capacity(rows * cols);
+    for (i, count) in counts.iter().enumerate() {
+        flat.extend(std::iter::repeat_n(i % 2 == 1, *count as usize));
+    }
+    encode_runs((0..cols).flat_map(|c| (0..rows).map(move |r| (r, c))).map(|(r, c)| flat[r * cols + c]))
+}
+
+/// Half-open intervals covered by both `a` and `b`.
+fn intersect(a: &[(usize, usize)], b: &[(usize, usize)]) -> Intervals {
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while (i < a.len()) & (j < b.len()) {
+        let start = a[i].0.max(b[j].0);
+        let end = a[i].1.min(b[j].1);
+        if start < end {
+            out.push((start, end));
+        }
+        if a[i].1 < b[j].1 { i += 1 } else { j += 1 }
+    }
+    out
+}
+
+/// Half-open intervals covered by `a` but not `b`.
+fn subtract(a: &[(usize, usize)], b: &[(usize, usize)]) -> Intervals {
+    let mut out = Vec::new();
+    let mut j = 0;
+    for &(start, end) in a {
+        let mut start = start;
+        while (j < b.len()) && (b[j].1 <= start) {
+            j += 1;
+        }
+        let mut k = j;
+        while (k < b.len()) && (b[k].0 < end) {
+            if b[k].0 > start {
+                out.push((start, b[k].0));
+            }
+            start = start.max(b[k].1);
+            k += 1;
+        }
+        if start < end {
+            out.push((start, end));
+        }
+    }
+    out
+}
+
+fn total_length(a: &[(usize, usize)]) -> u64 {
+    a.iter().map(|(start, end)| (end - start) as u64).sum()
+}
+
+impl RleMask {
+    /// None if the runs do not add up to the number of voxels.
+    pub fn from_counts(shape: [usize; 3], counts: Vec<u32>) -> Option<RleMask> {
+        let total: u64 = counts.iter().map(|c| *c as u64).sum();
+        if total != shape.iter().product::<usize>() as u64 {
+            return None;
+        }
+        Some(RleMask { shape, counts })
+    }
+    /// Mask from one RLE per z-slice, each encoding a y-x slice in the given order.
+    pub fn from_slices(shape: [usize; 3], slices: &[Vec<u32>], order: SliceOrder) -> Option<RleMask> {
+        if slices.len() != shape[0] {
+            return None;
+        }
+        let slice_size = (shape[1] * shape[2]) as u64;
+        let mut intervals = Vec::new();
+        for (z, counts) in slices.iter().enumerate() {
+            if counts.iter().map(|c| *c as u64).sum::<u64>() != slice_size {
+                return None;
+            }
+            let counts = match order {
+                SliceOrder::C => counts.clone(),
+                SliceOrder::Fortran => transpose_runs(counts, shape[2], shape[1]),
+            };
+            let mut pos = z * shape[1] * shape[2];
+            for (i, count) in counts.iter().enumerate() {
+                if (i % 2 == 1) & (*count > 0) {
+                    intervals.push((pos, pos + *count as usize));
+                }
+                pos += *count as usize;
+            }
+        }
+        Some(RleMask::from_intervals(shape, &intervals))
+    }
+    pub fn from_array(arr: &Array<bool, Ix3>) -> RleMask {
+        let shape = arr.shape();
+        RleMask { shape: [shape[0], shape[1], shape[2]], counts: encode_runs(arr.iter().copied()) }
+    }
+    /// Foreground intervals must be sorted and disjoint; touching intervals are merged.
+    fn from_intervals(shape: [usize; 3], intervals: &[(usize, usize)]) -> RleMask {
+        let mut counts = Vec::new();
+        let mut pos = 0;
+        for &(start, end) in intervals {
+            if (start == pos) & !counts.is_empty() {
+                *counts.last_mut().unwrap() += (end - start) as u32;
+            } else {
+                counts.push((start - pos) as u32);
+                counts.push((end - start) as u32);
+            }
+            pos = end;
+        }
+        let remaining = shape.iter().product::<usize>() - pos;
+        if (remaining > 0) | counts.is_empty() {
+            counts.push(remaining as u32);
+        }
+        RleMask { shape, counts }
+    }
+    pub fn to_array(&self) -> Array<bool, Ix3> {
+        let mut flat = Vec::with_capacity(self.shape.iter().product());
+        for (i, count) in self.counts.iter().enumerate() {
+            flat.extend(std::iter::repeat_n(i % 2 == 1, *count as usize));
+        }
+        Array::from_shape_vec(self.shape, flat).unwrap()
+    }
+    pub fn shape(&self) -> [usize; 3] {
+        self.shape
+    }
+    pub fn counts(&self) -> &[u32] {
+        &self.counts
+    }
+    /// One RLE per z-slice in the given order, the inverse of `from_slices`.
+    pub fn slices(&self, order: SliceOrder) -> Vec<Vec<u32>> {
+        let slice_size = self.shape[1] * self.shape[2];
+        let mut per_slice: Vec<Intervals> = vec![Vec::new(); self.shape[0]];
+        for (start, end) in self.intervals() {
+            let mut start = start;
+            while start < end {
+                let z = start / slice_size;
+                let split = end.min((z + 1) * slice_size);
+                per_slice[z].push((start - z * slice_size, split - z * slice_size));
+                start = split;
+            }
+        }
+        per_slice.iter()
+            .map(|intervals| {
+                let counts = RleMask::from_intervals([1, self.shape[1], self.shape[2]], intervals).counts;
+                match order {
+                    SliceOrder::C => counts,
+                    SliceOrder::Fortran => transpose_runs(&counts, self.shape[1], self.shape[2]),
+                }
+            })
+            .collect()
+    }
+    /// Number of foreground voxels.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().skip(1).step_by(2).map(|c| *c as u64).sum()
+    }
+    /// Foreground voxels as half-open intervals of flat indices.
+    fn intervals(&self) -> Intervals {
+        let mut out = Vec::new();
+        let mut pos = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            let end = pos + *count as usize;
+            if (i % 2 == 1) & (end > pos) {
+                out.push((pos, end));
+            }
+            pos = end;
+        }
+        out
+    }
+    /// Foreground x-intervals of every non-empty row, keyed by row index z * ny + y.
+    fn rows(&self) -> BTreeMap<usize, Intervals> {
+        let nx = self.shape[2];
+        let mut rows = BTreeMap::<usize, Intervals>::new();
+        for (start, end) in self.intervals() {
+            let mut start = start;
+            while start < end {
+                let row = start / nx;
+                let split = end.min((row + 1) * nx);
+                rows.entry(row).or_default().push((start - row * nx, split - row * nx));
+                start = split;
+            }
+        }
+        rows
+    }
+    /// In-plane edge of every row, matching `generate_edge(arr, true)`: foreground voxels
+    /// not eroded by the 3x3 box, with voxels outside the slice counting as background.
+    fn edge_rows(&self) -> BTreeMap<usize, Intervals> {
+        let ny = self.shape[1];
+        let rows = self.rows();
+        let eroded_x: BTreeMap<usize, Intervals> = rows.iter()
+            .map(|(row, intervals)| {
+                let eroded = intervals.iter()
+                    .filter(|(start, end)| end - start > 2)
+                    .map(|(start, end)| (start + 1, end - 1))
+                    .collect();
+                (*row, eroded)
+            })
+            .collect();
+        let empty = Vec::new();
+        rows.iter()
+            .map(|(row, intervals)| {
+                let y = row % ny;
+                let above = if y > 0 { eroded_x.get(&(row - 1)).unwrap_or(&empty) } else { &empty };
+                let below = if y + 1 < ny { eroded_x.get(&(row + 1)).unwrap_or(&empty) } else { &empty };
+                let eroded = intersect(&intersect(&eroded_x[row], above), below);
+                (*row, subtract(intervals, &eroded))
+            })
+            .collect()
+    }
+}
+
+/// Executed confusion matrix of two RLE masks of the same shape.
+pub fn confusion_matrix(ref_rle: &RleMask, other_rle: &RleMask) -> ConfusionMatrix {
+    assert_eq!(ref_rle.shape, other_rle.shape);
+    let tpos = total_length(&intersect(&ref_rle.intervals(), &other_rle.intervals()));
+    let fneg = ref_rle.count() - tpos;
+    let fpos = other_rle.count() - tpos;
+    let tneg = ref_rle.shape.iter().product::<usize>() as u64 - tpos - fneg - fpos;
+    ConfusionMatrix::from_counts(tpos as u32, tneg as u32, fpos as u32, fneg as u32)
+}
+
+/// Executed APL of two RLE masks of the same shape.
+pub fn apl(ref_rle: &RleMask, other_rle: &RleMask) -> APL {
+    assert_eq!(ref_rle.shape, other_rle.shape);
+    let ref_edges = ref_rle.edge_rows();
+    let empty = Vec::new();
+    let mut diff_count = 0;
+    let mut other_total_count = 0;
+    for (row, other_edge) in other_rle.edge_rows() {
+        other_total_count += total_length(&other_edge);
+        diff_count += total_length(&subtract(&other_edge, ref_edges.get(&row).unwrap_or(&empty)));
+    }
+    APL::from_counts(diff_count as u32, other_total_count as u32)
+}
+
+#[cfg(test)]
+mod test_rle {
+    use ndarray::{s, Array3};
+    use crate::apl::APL;
+    use crate::overlap::ConfusionMatrix;
+    use crate::rle::{apl, confusion_matrix, subtract, RleMask, SliceOrder};
+
+    fn generate_src_dst_arrays() -> (Array3<bool>, Array3<bool>) {
+        let mut src = Array3::<bool>::from_elem([4, 7, 9], false);
+        let mut dst = Array3::<bool>::from_elem([4, 7, 9], false);
+        src.slice_mut(s![0..3, 1..6, 2..9]).fill(true);
+        src[[3, 6, 8]] = true;
+        dst.slice_mut(s![1..4, 0..5, 1..7]).fill(true);
+        dst[[2, 2, 3]] = false;
+        (src, dst)
+    }
+
+    #[test]
+    fn test_rle_roundtrip() {
+        let (src, _) = generate_src_dst_arrays();
+        let rle = RleMask::from_array(&src);
+        assert_eq!(rle.to_array(), src);
+        assert_eq!(rle.count(), 106);
+        for order in [SliceOrder::C, SliceOrder::Fortran] {
+            assert_eq!(RleMask::from_slices(rle.shape(), &rle.slices(order), order), Some(rle.clone()));
+        }
+        assert_eq!(RleMask::from_counts(rle.shape(), rle.counts().to_vec()), Some(rle));
+        assert_eq!(RleMask::from_counts([2, 2, 2], vec![3, 4]), None);
+        assert_eq!(subtract(&[(0, 10)], &[(2, 3), (5, 7)]), vec![(0, 2), (3, 5), (7, 10)]);
+    }
+    #[test]
+    fn test_rle_coco_slice() {
+        // 2x3 slice [[1, 0, 0], [1, 1, 0]]; pycocotools encodes it column by column,
+        // giving the uncompressed counts [0, 2, 1, 1, 2].
+        let mut arr = Array3::<bool>::from_elem([1, 2, 3], false);
+        arr[[0, 0, 0]] = true;
+        arr[[0, 1, 0]] = true;
+        arr[[0, 1, 1]] = true;
+        let rle = RleMask::from_array(&arr);
+        assert_eq!(rle.counts(), &[0, 1, 2, 2, 1]);
+        assert_eq!(rle.slices(SliceOrder::Fortran), vec![vec![0, 2, 1, 1, 2]]);
+        assert_eq!(RleMask::from_slices([1, 2, 3], &[vec![0, 2, 1, 1, 2]], SliceOrder::Fortran), Some(rle));
+    }
+    #[test]
+    fn test_rle_metrics_match_dense() {
+        let (src, dst) = generate_src_dst_arrays();
+        let (ref_rle, other_rle) = (RleMask::from_array(&src), RleMask::from_array(&dst));
+
+        let mut dense = ConfusionMatrix::new(&src, &dst);
+        dense.execute();
+        let cm = confusion_matrix(&ref_rle, &other_rle);
+        assert_eq!((cm.tpos(), cm.tneg(), cm.fpos(), cm.fneg()), (dense.tpos(), dense.tneg(), dense.fpos(), dense.fneg()));
+        assert_eq!((cm.dc(), cm.jc()), (dense.dc(), dense.jc()));
+
+        let mut dense = APL::new(&src, &dst);
+        dense.execute();
+        let rle_apl = apl(&ref_rle, &other_rle);
+        assert_eq!(rle_apl.apl(), dense.apl());
+        assert_eq!(rle_apl.apl_normalized(), dense.apl_normalized());
+    }
+}
@@ -0,0 +1,271 @@
+use std::f64::consts::PI;
+use ndarray::{Array, Ix3};
+use crate::mesh::Mesh;
+use crate::utils::bounding_box;
+
+/// Eigenvalues (descending) and eigenvectors (rows) of a symmetric 3x3 matrix, by Jacobi rotations.
+fn symmetric_eigen(mut a: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for _ in 0..50 {
+        let off = a[0][1].powi(2) + a[0][2].powi(2) + a[1][2].powi(2);
+        if off < 1e-20 {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q].abs() < 1e-300 {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let t = if theta == 0.0 { 1.0 } else { t };
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+            for row in a.iter_mut() {
+                let akp = row[p];
+                let akq = row[q];
+                row[p] = c * akp - s * akq;
+                row[q] = s * akp + c * akq;
+            }
+            let (row_p, row_q) = (a[p], a[q]);
+            a[p] = [0, 1, 2].map(|k| c * row_p[k] - s * row_q[k]);
+            a[q] = [0, 1, 2].map(|k| s * row_p[k] + c * row_q[k]);
+            for row in v.iter_mut() {
+                let vp = row[p];
+                let vq = row[q];
+                row[p] = c * vp - s * vq;
+                row[q] = s * vp + c * vq;
+            }
+        }
+    }
+    let mut order = [0, 1, 2];
+    order.sort_by(|i, j| a[*j][*j].partial_cmp(&a[*i][*i]).unwrap());
+    let values = [a[order[0]][order[0]], a[order[1]][order[1]], a[order[2]][order[2]]];
+    let vectors = [
+        [v[0][order[0]], v[1][order[0]], v[2][order[0]]],
+        [v[0][order[1]], v[1][order[1]], v[2][order[1]]],
+        [v[0][order[2]], v[1][order[2]], v[2][order[2]]],
+    ];
+    (values, vectors)
+}
+
+/// How `ShapeDescriptors` measures the surface area.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SurfaceAreaMethod {
+    /// Total area of voxel faces between foreground and background. Overestimates smooth
+    /// and oblique surfaces, e.g. by about 50% for a sphere.
+    VoxelFaces,
+    /// Area of the triangle mesh from `mesh::Mesh`.
+    Mesh,
+}
+
+/// Shape features of a single mask in physical units (mm, mm², mm³). The surface area
+/// is measured as set by `with_surface_area_method`, on voxel faces by default. Centroid,
+/// extent and bounds are NaN for an empty mask.
+pub struct ShapeDescriptors {
+    arr: Array<bool, Ix3>,
+    zyx_spacing: Vec<f32>,
+    surface_area_method: SurfaceAreaMethod,
+    n_voxels: u64,
+    surface_area: f64,
+    centroid: [f64; 3],
+    eigenvalues: [f64; 3],
+    principal_axes: [[f64; 3]; 3],
+    extent: [f64; 3],
+    bounds: [[f64; 2]; 3],
+}
+
+impl ShapeDescriptors {
+    pub fn new(arr: &Array<bool, Ix3>, zyx_spacing: Vec<f32>) -> ShapeDescriptors {
+        ShapeDescriptors {
+            arr: arr.clone(),
+            zyx_spacing,
+            surface_area_method: SurfaceAreaMethod::VoxelFaces,
+            n_voxels: 0,
+            surface_area: 0.0,
+            centroid: [0.0; 3],
+            eigenvalues: [0.0; 3],
+            principal_axes: [[0.0; 3]; 3],
+            extent: [0.0; 3],
+            bounds: [[0.0; 2]; 3],
+        }
+    }
+    pub fn with_surface_area_method(mut self, method: SurfaceAreaMethod) -> ShapeDescriptors {
+        self.surface_area_method = method;
+        self
+    }
+    pub fn execute(&mut self) {
+        let spacing: Vec<f64> = self.zyx_spacing.iter().map(|s| *s as f64).collect();
+        let face_areas = [spacing[1] * spacing[2], spacing[0] * spacing[2], spacing[0] * spacing[1]];
+        let shape = self.arr.shape();
+
+        let mut sums = [0.0_f64; 3];
+        let mut products = [[0.0_f64; 3]; 3];
+        for ((z, y, x), val) in self.arr.indexed_iter() {
+            if !*val {
+                continue;
+            }
+            self.n_voxels += 1;
+            let idx = [z, y, x];
+            let point = [z as f64 * spacing[0], y as f64 * spacing[1], x as f64 * spacing[2]];
+            for (i, (sum, row)) in sums.iter_mut().zip(products.iter_mut()).enumerate() {
+                *sum += point[i];
+                for (product, p) in row.iter_mut().zip(point.iter()) {
+                    *product += point[i] * p;
+                }
+                for step in [-1_isize, 1] {
+                    let mut neighbour = idx;
+                    let n = idx[i] as isize + step;
+                    if (n < 0) | (n >= shape[i] as isize) {
+                        self.surface_area += face_areas[i];
+                        continue;
+                    }
+                    neighbour[i] = n as usize;
+                    if !self.arr[neighbour] {
+                        self.surface_area += face_areas[i];
+                    }
+                }
+            }
+        }
+        if self.surface_area_method == SurfaceAreaMethod::Mesh {
+            let mut mesh = Mesh::new(&self.arr, self.zyx_spacing.clone());
+            mesh.execute();
+            self.surface_area = mesh.surface_area() as f64;
+        }
+        if self.n_voxels == 0 {
+            // No position for an empty mask
+            self.centroid = [f64::NAN; 3];
+            self.extent = [f64::NAN; 3];
+            self.bounds = [[f64::NAN; 2]; 3];
+            return;
+        }
+        let n = self.n_voxels as f64;
+        let mut covariance = [[0.0_f64; 3]; 3];
+        self.centroid = sums.map(|sum| sum / n);
+        for i in 0..3 {
+            for j in 0..3 {
+                covariance[i][j] = products[i][j] / n - self.centroid[i] * self.centroid[j];
+            }
+        }
+        let (values, vectors) = symmetric_eigen(covariance);
+        self.eigenvalues = values.map(|v| v.max(0.0));
+        self.principal_axes = vectors;
+
+        let bbox = bounding_box(&self.arr).unwrap();
+        for i in 0..3 {
+            self.extent[i] = (bbox[i][1] - bbox[i][0] + 1) as f64 * spacing[i];
+            self.bounds[i] = [bbox[i][0] as f64 * spacing[i], bbox[i][1] as f64 * spacing[i]];
+        }
+    }
+    /// Volume in mm³.
+    pub fn volume(&self) -> f32 {
+        (self.n_voxels as f64 * self.zyx_spacing.iter().map(|s| *s as f64).product::<f64>()) as f32
+    }
+    /// Surface area in mm².
+    pub fn surface_area(&self) -> f32 {
+        self.surface_area as f32
+    }
+    /// Ratio of the surface area of a sphere with the same volume to the actual surface area.
+    pub fn sphericity(&self) -> f32 {
+        let volume = self.volume() as f64;
+        (PI.cbrt() * (6.0 * volume).powf(2.0 / 3.0) / self.surface_area) as f32
+    }
+    /// 36 π V² / A³ (equals sphericity cubed).
+    pub fn compactness(&self) -> f32 {
+        let volume = self.volume() as f64;
+        (36.0 * PI * volume.powi(2) / self.surface_area.powi(3)) as f32
+    }
+    /// sqrt(λ_minor / λ_major) of the coordinate covariance.
+    pub fn elongation(&self) -> f32 {
+        (self.eigenvalues[1] / self.eigenvalues[0]).sqrt() as f32
+    }
+    /// sqrt(λ_least / λ_major) of the coordinate covariance.
+    pub fn flatness(&self) -> f32 {
+        (self.eigenvalues[2] / self.eigenvalues[0]).sqrt() as f32
+    }
+    /// Centre of mass in mm, zyx order, relative to the centre of voxel [0, 0, 0].
+    pub fn centroid(&self) -> [f32; 3] {
+        self.centroid.map(|c| c as f32)
+    }
+    /// Unit vectors (zyx) of the principal axes, from major to least.
+    pub fn principal_axes(&self) -> [[f32; 3]; 3] {
+        self.principal_axes.map(|axis| axis.map(|c| c as f32))
+    }
+    /// Standard deviation (mm) along each principal axis, from major to least.
+    pub fn principal_lengths(&self) -> [f32; 3] {
+        self.eigenvalues.map(|v| v.sqrt() as f32)
+    }
+    /// Bounding box size in mm along z, y and x.
+    pub fn extent(&self) -> [f32; 3] {
+        self.extent.map(|e| e as f32)
+    }
+    /// Position (mm) of the first and last foreground voxel centre along z, y and x.
+    pub fn bounds(&self) -> [[f32; 2]; 3] {
+        self.bounds.map(|b| b.map(|v| v as f32))
+    }
+}
+
+#[cfg(test)]
+mod test_shape {
+    use std::f32::consts::PI;
+    use ndarray::Array3;
+    use crate::shape::{ShapeDescriptors, SurfaceAreaMethod};
+
+    #[test]
+    fn test_box_descriptors() {
+        let mut src = Array3::<bool>::from_elem([4, 6, 8], false);
+        for z in 1..3 {
+            for y in 1..5 {
+                for x in 0..8 {
+                    src[[z, y, x]] = true;
+                }
+            }
+        }
+        let mut shape = ShapeDescriptors::new(&src, vec![2.0, 1.0, 0.5]);
+        shape.execute();
+
+        // 4 x 4 x 4 mm cube
+        assert_eq!(shape.volume(), 64.0);
+        assert_eq!(shape.surface_area(), 96.0);
+        assert_eq!(shape.extent(), [4.0, 4.0, 4.0]);
+        assert_eq!(shape.bounds(), [[2.0, 4.0], [1.0, 4.0], [0.0, 3.5]]);
+        assert_eq!(shape.centroid(), [3.0, 2.5, 1.75]);
+        assert!((shape.sphericity() - 0.8059958).abs() < 1e-5);
+        assert!((shape.compactness() - shape.sphericity().powi(3)).abs() < 1e-5);
+    }
+    #[test]
+    fn test_principal_axes() {
+        let mut src = Array3::<bool>::from_elem([3, 3, 12], false);
+        for x in 0..12 {
+            src[[1, 1, x]] = true;
+        }
+        src[[1, 0, 5]] = true;
+        src[[1, 2, 5]] = true;
+        src[[1, 0, 6]] = true;
+        src[[1, 2, 6]] = true;
+        let mut shape = ShapeDescriptors::new(&src, vec![1.0, 1.0, 1.0]);
+        shape.execute();
+
+        let major = shape.principal_axes()[0];
+        assert!((major[2].abs() - 1.0).abs() < 1e-5);
+        assert!(shape.elongation() < 0.5);
+        assert_eq!(shape.flatness(), 0.0);
+    }
+    #[test]
+    fn test_mesh_surface_area() {
+        let radius = 8.0_f32;
+        let src = Array3::<bool>::from_shape_fn([19, 19, 19], |(z, y, x)| {
+            let d = [z, y, x].map(|i| i as f32 - 9.0);
+            (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt() <= radius
+        });
+        let mut voxel = ShapeDescriptors::new(&src, vec![1.0, 1.0, 1.0]);
+        voxel.execute();
+        let mut mesh = ShapeDescriptors::new(&src, vec![1.0, 1.0, 1.0]).with_surface_area_method(SurfaceAreaMethod::Mesh);
+        mesh.execute();
+
+        let sphere = 4.0 * PI * radius * radius;
+        assert!((voxel.surface_area() - sphere).abs() > 0.3 * sphere);
+        assert!((mesh.surface_area() - sphere).abs() < (voxel.surface_area() - sphere).abs());
+        assert_eq!(mesh.volume(), voxel.volume());
+        assert!(mesh.sphericity() > voxel.sphericity());
+    }
+}
@@ -0,0 +1,164 @@
+use ndarray::{Array, Ix3, Zip};
+use crate::overlap::ConfusionMatrix;
+use crate::reference::ReferenceSurface;
+
+/// Overlap metrics between a boolean reference and a voxelwise probability map,
+/// without thresholding the probabilities.
+pub struct SoftOverlap {
+    ref_arr: Array<bool, Ix3>,
+    prob_arr: Array<f32, Ix3>,
+    intersection: f64,
+    ref_sum: f64,
+    prob_sum: f64,
+}
+
+impl SoftOverlap {
+    pub fn new(ref_arr: &Array<bool, Ix3>, prob_arr: &Array<f32, Ix3>) -> SoftOverlap {
+        assert_eq!(ref_arr.shape(), prob_arr.shape(), "reference and probability map must have the same shape");
+        SoftOverlap {
+            ref_arr: ref_arr.clone(),
+            prob_arr: prob_arr.clone(),
+            intersection: 0.0,
+            ref_sum: 0.0,
+            prob_sum: 0.0,
+        }
+    }
+    pub fn execute(&mut self) {
+        for (ref_val, prob) in self.ref_arr.iter().zip(self.prob_arr.iter()) {
+            let prob = *prob as f64;
+            self.prob_sum += prob;
+            if *ref_val {
+                self.ref_sum += 1.0;
+                self.intersection += prob;
+            }
+        }
+    }
+    pub fn dc(&self) -> f32 {
+        (2.0 * self.intersection / (self.prob_sum + self.ref_sum)) as f32
+    }
+    pub fn jc(&self) -> f32 {
+        (self.intersection / (self.prob_sum + self.ref_sum - self.intersection)) as f32
+    }
+    /// Tversky index; `alpha` weighs soft false positives, `beta` soft false negatives.
+    pub fn tversky(&self, alpha: &f32, beta: &f32) -> f32 {
+        let fpos = self.prob_sum - self.intersection;
+        let fneg = self.ref_sum - self.intersection;
+        (self.intersection / (self.intersection + *alpha as f64 * fpos + *beta as f64 * fneg)) as f32
+    }
+}
+
+/// Binary metrics of a probability map thresholded (prob >= threshold) at several
+/// thresholds. Confusion counts for all thresholds are gathered in a single pass.
+pub struct ThresholdSweep {
+    ref_arr: Array<bool, Ix3>,
+    prob_arr: Array<f32, Ix3>,
+    zyx_spacing: Vec<f32>,
+    thresholds: Vec<f32>,
+    cms: Vec<ConfusionMatrix>,
+    hds: Vec<f32>,
+}
+
+impl ThresholdSweep {
+    pub fn new(ref_arr: &Array<bool, Ix3>,
+               prob_arr: &Array<f32, Ix3>,
+               zyx_spacing: Vec<f32>,
+               thresholds: Vec<f32>) -> ThresholdSweep {
+        assert_eq!(ref_arr.shape(), prob_arr.shape(), "reference and probability map must have the same shape");
+        ThresholdSweep {
+            ref_arr: ref_arr.clone(),
+            prob_arr: prob_arr.clone(),
+            zyx_spacing,
+            thresholds,
+            cms: Vec::new(),
+            hds: Vec::new(),
+        }
+    }
+    pub fn execute(&mut self) {
+        let n = self.thresholds.len();
+        let (mut tpos, mut tneg, mut fpos, mut fneg) = (vec![0_u32; n], vec![0_u32; n], vec![0_u32; n], vec![0_u32; n]);
+        for (ref_val, prob) in self.ref_arr.iter().zip(self.prob_arr.iter()) {
+            for (i, t) in self.thresholds.iter().enumerate() {
+                match (*ref_val, prob >= t) {
+                    (true, true) => tpos[i] += 1,
+                    (false, false) => tneg[i] += 1,
+                    (false, true) => fpos[i] += 1,
+                    (true, false) => fneg[i] += 1,
+                }
+            }
+        }
+        for i in 0..n {
+            self.cms.push(ConfusionMatrix::from_counts(tpos[i], tneg[i], fpos[i], fneg[i]));
+        }
+
+        // The reference surface and its distance transform are shared by all thresholds
+        let reference = ReferenceSurface::new(&self.ref_arr, self.zyx_spacing.clone());
+        let ref_empty = reference.bounding_box().is_none();
+        for (i, t) in self.thresholds.iter().enumerate() {
+            if ref_empty | (tpos[i] + fpos[i] == 0) {
+                self.hds.push(f32::NAN);
+                continue;
+            }
+            let other_arr = Zip::from(&self.prob_arr).map_collect(|p| p >= t);
+            let hd_map = reference.hausdorff_map(&other_arr).unwrap();
+            self.hds.push(hd_map.hd());
+        }
+    }
+    pub fn thresholds(&self) -> &[f32] {
+        &self.thresholds
+    }
+    pub fn dc(&self) -> Vec<f32> {
+        self.cms.iter().map(|cm| cm.dc()).collect()
+    }
+    pub fn jc(&self) -> Vec<f32> {
+        self.cms.iter().map(|cm| cm.jc()).collect()
+    }
+    /// Undirected Hausdorff distance per threshold; NaN where either mask is empty.
+    pub fn hd(&self) -> &[f32] {
+        &self.hds
+    }
+}
+
+#[cfg(test)]
+mod test_soft {
+    use ndarray::{Array, Array3, Ix3};
+    use crate::soft::{SoftOverlap, ThresholdSweep};
+
+    fn generate_ref_prob_arrays() -> (Array<bool, Ix3>, Array<f32, Ix3>) {
+        let src = Array3::<u8>::from(vec![
+               [[1, 1, 0, 0],
+                [1, 1, 0, 0]]]);
+        let prob = Array3::<f32>::from(vec![
+               [[1.0, 0.5, 0.5, 0.0],
+                [1.0, 0.5, 0.0, 0.0]]]);
+        (src.mapv(|x| x != 0), prob)
+    }
+
+    #[test]
+    fn test_soft_overlap() {
+        let (src, prob) = generate_ref_prob_arrays();
+        let mut soft = SoftOverlap::new(&src, &prob);
+        soft.execute();
+
+        assert_eq!(soft.dc(), 0.8);
+        assert_eq!(soft.jc(), 0.6666667);
+        assert_eq!(soft.tversky(&0.5, &0.5), soft.dc());
+        assert_eq!(soft.tversky(&1.0, &1.0), soft.jc());
+    }
+    #[test]
+    fn test_threshold_sweep() {
+        let (src, prob) = generate_ref_prob_arrays();
+        let mut sweep = ThresholdSweep::new(&src, &prob, vec![1.0, 1.0, 1.0], vec![0.25, 0.75, 1.5]);
+        sweep.execute();
+
+        assert_eq!(sweep.dc(), vec![0.8888889, 0.6666667, 0.0]);
+        assert_eq!(sweep.jc()[..2], [0.8, 0.5]);
+        assert_eq!(sweep.hd()[..2], [1.0, 1.0]);
+        assert!(sweep.hd()[2].is_nan());
+    }
+    #[test]
+    #[should_panic(expected = "reference and probability map must have the same shape")]
+    fn test_shape_mismatch() {
+        let (ref_arr, _) = generate_ref_prob_arrays();
+        SoftOverlap::new(&ref_arr, &Array3::<f32>::zeros([1, 2, 3]));
+    }
+}
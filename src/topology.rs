@@ -0,0 +1,253 @@
+use ndarray::{s, Array, Array3, Ix3};
+use crate::utils::{joint_crop, label_components};
+
+/// Betti numbers and Euler characteristic of a mask. `connectivity` (6 or 26) applies to
+/// the foreground; the background uses the complementary connectivity (26 or 6). 18 is not
+/// supported because it has no complementary connectivity that keeps the Euler formula valid.
+/// b0 counts connected components, b1 tunnels (handles) and b2 enclosed cavities.
+pub struct Topology<'a> {
+    arr: &'a Array<bool, Ix3>,
+    connectivity: u8,
+    betti: [u32; 3],
+    euler: i64,
+}
+
+/// Euler characteristic of the union of closed unit cubes (26-connected foreground):
+/// V - E + F - C. Every lattice point owns its vertex, the three edges and three faces
+/// that start at it and the cube in its positive octant, so each contribution is read
+/// from the 2x2x2 voxel configuration around that point.
+fn euler_closed_cubes(arr: &Array<bool, Ix3>) -> i64 {
+    let shape = arr.shape();
+    let at = |z: usize, y: usize, x: usize| {
+        (z > 0) & (y > 0) & (x > 0) && arr.get([z - 1, y - 1, x - 1]).copied().unwrap_or(false)
+    };
+    let mut euler = 0;
+    for z in 0..shape[0] + 1 {
+        for y in 0..shape[1] + 1 {
+            for x in 0..shape[2] + 1 {
+                // v[dz][dy][dx] is the voxel at (z - 1 + dz, y - 1 + dy, x - 1 + dx)
+                let v = [0, 1].map(|dz| [0, 1].map(|dy| [0, 1].map(|dx| at(z + dz, y + dy, x + dx))));
+                let any = |cells: &[(usize, usize, usize)]| cells.iter().any(|&(dz, dy, dx)| v[dz][dy][dx]);
+                if !v.iter().flatten().flatten().any(|v| *v) {
+                    continue;
+                }
+                let edges = [
+                    any(&[(0, 0, 1), (0, 1, 1), (1, 0, 1), (1, 1, 1)]),
+                    any(&[(0, 1, 0), (0, 1, 1), (1, 1, 0), (1, 1, 1)]),
+                    any(&[(1, 0, 0), (1, 0, 1), (1, 1, 0), (1, 1, 1)]),
+                ];
+                let faces = [
+                    any(&[(0, 1, 1), (1, 1, 1)]),
+                    any(&[(1, 0, 1), (1, 1, 1)]),
+                    any(&[(1, 1, 0), (1, 1, 1)]),
+                ];
+                euler += 1;
+                euler -= edges.iter().filter(|v| **v).count() as i64;
+                euler += faces.iter().filter(|v| **v).count() as i64;
+                euler -= v[1][1][1] as i64;
+            }
+        }
+    }
+    euler
+}
+
+/// Euler characteristic of the complex with voxels as vertices (6-connected foreground):
+/// voxels - 6-adjacent pairs + 2x2 squares - 2x2x2 cubes.
+fn euler_voxel_complex(arr: &Array<bool, Ix3>) -> i64 {
+    let at = |z: usize, y: usize, x: usize| arr.get([z, y, x]).copied().unwrap_or(false);
+    let mut euler = 0;
+    for ((z, y, x), val) in arr.indexed_iter() {
+        if !*val {
+            continue;
+        }
+        euler += 1;
+        euler -= [at(z + 1, y, x), at(z, y + 1, x), at(z, y, x + 1)].iter().filter(|v| **v).count() as i64;
+        let squares = [
+            at(z, y + 1, x) & at(z, y, x + 1) & at(z, y + 1, x + 1),
+            at(z + 1, y, x) & at(z, y, x + 1) & at(z + 1, y, x + 1),
+            at(z + 1, y, x) & at(z, y + 1, x) & at(z + 1, y + 1, x),
+        ];
+        euler += squares.iter().filter(|v| **v).count() as i64;
+        if squares.iter().all(|v| *v) & at(z + 1, y + 1, x + 1) {
+            euler -= 1;
+        }
+    }
+    euler
+}
+
+impl<'a> Topology<'a> {
+    pub fn new(arr: &'a Array<bool, Ix3>, connectivity: u8) -> Topology<'a> {
+        assert!((connectivity == 6) | (connectivity == 26), "connectivity must be 6 or 26, got {}", connectivity);
+        Topology {
+            arr,
+            connectivity,
+            betti: [0; 3],
+            euler: 0,
+        }
+    }
+    /// Works on the bounding box of the foreground: the background outside it is connected
+    /// to the outside anyway, so neither cavities nor the Euler characteristic change.
+    pub fn execute(&mut self) {
+        let Some([zs, ys, xs]) = joint_crop(&[self.arr], 0) else {
+            self.betti = [0; 3];
+            self.euler = 0;
+            return;
+        };
+        let arr = self.arr.slice(s![zs, ys, xs]).to_owned();
+        let (background_connectivity, euler) = match self.connectivity {
+            26 => (6, euler_closed_cubes(&arr)),
+            _ => (26, euler_voxel_complex(&arr)),
+        };
+        let (_, n_components) = label_components(&arr, self.connectivity);
+
+        // Pad with background so that everything outside forms a single component
+        let shape = arr.shape();
+        let mut background = Array3::<bool>::from_elem((shape[0] + 2, shape[1] + 2, shape[2] + 2), true);
+        background.slice_mut(s![1..shape[0] + 1, 1..shape[1] + 1, 1..shape[2] + 1]).assign(&arr.mapv(|v| !v));
+        let (_, n_background) = label_components(&background, background_connectivity);
+        let n_cavities = n_background - 1;
+
+        self.euler = euler;
+        self.betti = [
+            n_components,
+            (n_components as i64 + n_cavities as i64 - euler) as u32,
+            n_cavities,
+        ];
+    }
+    /// [b0, b1, b2]: components, tunnels and cavities.
+    pub fn betti(&self) -> [u32; 3] {
+        self.betti
+    }
+    pub fn euler(&self) -> i64 {
+        self.euler
+    }
+}
+
+/// Absolute differences in Betti numbers and Euler characteristic between two masks.
+pub struct TopologyError<'a> {
+    ref_topology: Topology<'a>,
+    other_topology: Topology<'a>,
+}
+
+impl<'a> TopologyError<'a> {
+    pub fn new(ref_arr: &'a Array<bool, Ix3>, other_arr: &'a Array<bool, Ix3>, connectivity: u8) -> TopologyError<'a> {
+        TopologyError {
+            ref_topology: Topology::new(ref_arr, connectivity),
+            other_topology: Topology::new(other_arr, connectivity),
+        }
+    }
+    pub fn execute(&mut self) {
+        self.ref_topology.execute();
+        self.other_topology.execute();
+    }
+    pub fn ref_betti(&self) -> [u32; 3] {
+        self.ref_topology.betti()
+    }
+    pub fn other_betti(&self) -> [u32; 3] {
+        self.other_topology.betti()
+    }
+    pub fn betti_error(&self) -> [u32; 3] {
+        let ref_betti = self.ref_betti();
+        let other_betti = self.other_betti();
+        [0, 1, 2].map(|i| ref_betti[i].abs_diff(other_betti[i]))
+    }
+    pub fn euler_error(&self) -> u64 {
+        self.ref_topology.euler().abs_diff(self.other_topology.euler())
+    }
+}
+
+#[cfg(test)]
+mod test_topology {
+    use ndarray::{s, Array3};
+    use crate::topology::{Topology, TopologyError};
+
+    #[test]
+    fn test_betti_numbers() {
+        // Solid block with an enclosed cavity
+        let mut hollow = Array3::<bool>::from_elem([5, 5, 5], false);
+        hollow.slice_mut(s![1..4, 1..4, 1..4]).fill(true);
+        hollow[[2, 2, 2]] = false;
+        // Ring (one tunnel) and a separate island
+        let mut ring = Array3::<bool>::from_elem([3, 7, 9], false);
+        ring.slice_mut(s![1, 1..6, 1..6]).fill(true);
+        ring[[1, 3, 3]] = false;
+        ring[[1, 3, 7]] = true;
+
+        for connectivity in [6, 26] {
+            let mut topology = Topology::new(&hollow, connectivity);
+            topology.execute();
+            assert_eq!(topology.betti(), [1, 0, 1]);
+            assert_eq!(topology.euler(), 2);
+
+            let mut topology = Topology::new(&ring, connectivity);
+            topology.execute();
+            assert_eq!(topology.betti(), [2, 1, 0]);
+            assert_eq!(topology.euler(), 1);
+        }
+    }
+    #[test]
+    fn test_empty_mask() {
+        let empty = Array3::<bool>::from_elem([3, 3, 3], false);
+        for connectivity in [6, 26] {
+            let mut topology = Topology::new(&empty, connectivity);
+            topology.execute();
+            assert_eq!(topology.betti(), [0, 0, 0]);
+            assert_eq!(topology.euler(), 0);
+        }
+    }
+    #[test]
+    fn test_connectivity_matters() {
+        let mut diagonal = Array3::<bool>::from_elem([3, 3, 3], false);
+        diagonal[[0, 0, 0]] = true;
+        diagonal[[1, 1, 1]] = true;
+
+        let mut topology = Topology::new(&diagonal, 26);
+        topology.execute();
+        assert_eq!(topology.betti(), [1, 0, 0]);
+
+        let mut topology = Topology::new(&diagonal, 6);
+        topology.execute();
+        assert_eq!(topology.betti(), [2, 0, 0]);
+    }
+    #[test]
+    fn test_topology_error() {
+        let mut src = Array3::<bool>::from_elem([6, 6, 6], false);
+        src.slice_mut(s![1..4, 1..4, 1..4]).fill(true);
+        let mut dst = src.clone();
+        dst[[2, 2, 2]] = false;
+        dst[[5, 5, 5]] = true;
+
+        let mut error = TopologyError::new(&src, &dst, 26);
+        error.execute();
+        assert_eq!(error.ref_betti(), [1, 0, 0]);
+        assert_eq!(error.other_betti(), [2, 0, 1]);
+        assert_eq!(error.betti_error(), [1, 0, 1]);
+        assert_eq!(error.euler_error(), 2);
+    }
+    #[test]
+    fn test_euler_closed_cubes() {
+        // Reference count on a grid of doubled resolution
+        fn doubled_grid_euler(arr: &Array3<bool>) -> i64 {
+            let shape = arr.shape();
+            let mut cells = Array3::<bool>::from_elem((2 * shape[0] + 1, 2 * shape[1] + 1, 2 * shape[2] + 1), false);
+            for ((z, y, x), val) in arr.indexed_iter() {
+                if *val {
+                    cells.slice_mut(s![2 * z..2 * z + 3, 2 * y..2 * y + 3, 2 * x..2 * x + 3]).fill(true);
+                }
+            }
+            cells.indexed_iter()
+                .filter(|(_, val)| **val)
+                .map(|((z, y, x), _)| if (z + y + x).is_multiple_of(2) { 1 } else { -1 })
+                .sum()
+        }
+        let mut arr = Array3::<bool>::from_elem([4, 5, 6], false);
+        let mut state: u32 = 12345;
+        for val in arr.iter_mut() {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            *val = (state >> 16).is_multiple_of(3);
+        }
+        arr[[0, 0, 0]] = true;
+        arr[[3, 4, 5]] = true;
+        assert_eq!(super::euler_closed_cubes(&arr), doubled_grid_euler(&arr));
+    }
+}
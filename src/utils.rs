@@ -1,63 +1,434 @@
-use ndarray::{Array, Array3, Ix3, s};
-fn clip_value(val: &usize, min: &usize, max: &usize) -> usize {
-    if val.lt(min) {
-        return min.to_owned()
-    } else if val.gt( max) {
-        return max.to_owned()
-    } else {
-        return val.to_owned()
+use std::collections::VecDeque;
+use std::ops::Range;
+use ndarray::{Array, Array3, Axis, Ix3, s};
+use crate::bitmask::BitMask;
+
+/// Smallest box holding the foreground of all `arrs`, grown by `margin` voxels and clipped
+/// to the volume, as half-open ranges. None if all arrays are empty. All arrays are scanned
+/// together in one pass over the rows, reading only the first and last foreground voxel of
+/// each row.
+pub fn joint_crop(arrs: &[&Array<bool, Ix3>], margin: usize) -> Option<[Range<usize>; 3]> {
+    let shape = arrs[0].shape();
+    let mut joint: Option<[[usize; 2]; 3]> = None;
+    for z in 0..shape[0] {
+        for y in 0..shape[1] {
+            let mut row_bounds: Option<[usize; 2]> = None;
+            for arr in arrs {
+                let row = arr.slice(s![z, y, ..]);
+                let Some(first) = row.iter().position(|v| *v) else { continue };
+                let last = row.iter().rposition(|v| *v).unwrap();
+                row_bounds = Some(match row_bounds {
+                    None => [first, last],
+                    Some([f, l]) => [f.min(first), l.max(last)],
+                });
+            }
+            if let Some([first, last]) = row_bounds {
+                joint = Some(match joint {
+                    None => [[z, z], [y, y], [first, last]],
+                    Some(j) => [[j[0][0], z], [j[1][0].min(y), j[1][1].max(y)], [j[2][0].min(first), j[2][1].max(last)]],
+                });
+            }
+        }
     }
+    joint.map(|j| [0, 1, 2].map(|d| j[d][0].saturating_sub(margin)..(j[d][1] + margin + 1).min(shape[d])))
 }
+
+/// Surface voxels of `arr`: foreground voxels with at least one background voxel in their
+/// 3x3x3 neighbourhood (3x3 in-plane if `use_2d`), see `BitMask::edge`. Only the bounding
+/// box of the foreground plus a one voxel margin is scanned; since the margin is clipped to
+/// the volume, voxels on the volume border are still treated as surface voxels.
 pub fn generate_edge(arr: &Array<bool, Ix3>, use_2d: bool) -> Array<bool, Ix3> {
-    let threshold_2d: u8 = 9;
-    let threshold_3d: u8 = 27;
-
-    let arr = arr.mapv(|arr| u8::from(arr));
-    let mut edge_arr = Array3::<u8>::zeros(arr.raw_dim());
-    let zmin = 0;
-    let zmax = arr.shape()[0];
-    let ymin = 0;
-    let ymax = arr.shape()[1];
-    let xmin = 0;
-    let xmax = arr.shape()[2];
-
-    for z in zmin..zmax {
-        for y in ymin..ymax {
-            for x in xmin..xmax {
-                let val = arr.get([z, y, x]).unwrap();
-                if val.eq(&u8::from(1)) {
-                    let slice_zmin = clip_value(&z.checked_sub(1).unwrap_or(0), &zmin, &zmax);
-                    let slice_zmax = clip_value(&(&z + 2).to_owned(), &zmin, &zmax);
-                    let slice_ymin = clip_value(&y.checked_sub(1).unwrap_or(0), &ymin, &ymax);
-                    let slice_ymax = clip_value(&(&y + 2).to_owned(), &ymin, &ymax);
-                    let slice_xmin = clip_value(&x.checked_sub(1).unwrap_or(0), &xmin, &xmax);
-                    let slice_xmax = clip_value(&(&x + 2).to_owned(), &xmin, &xmax);
-
-                    if use_2d {
-                        let sum: u8 = arr.slice(s![
-                            z,
-                            slice_ymin..slice_ymax,
-                            slice_xmin..slice_xmax])
-                            .iter().sum();
-                        if sum < threshold_2d {
-                            edge_arr[[z, y, x]] = arr[[z, y, x]];
-                        }
-                    } else {
-                        let sum: u8 = arr.slice(s![
-                            slice_zmin..slice_zmax,
-                            slice_ymin..slice_ymax,
-                            slice_xmin..slice_xmax])
-                            .iter().sum();
-                        if sum < threshold_3d {
-                            edge_arr[[z, y, x]] = arr[[z, y, x]];
-                        }
+    let mut edge_arr = Array3::<bool>::from_elem(arr.raw_dim(), false);
+    if let Some([zs, ys, xs]) = joint_crop(&[arr], 1) {
+        let crop = s![zs, ys, xs];
+        edge_arr.slice_mut(crop).assign(&BitMask::from_view(arr.slice(crop)).edge(use_2d).to_array());
+    }
+    edge_arr
+}
+
+/// Contiguous range of slices along `axis` spanned by the non-empty slices of `arr`,
+/// widened by `margin` (in mm) on both sides. Empty if `arr` has no foreground.
+pub fn reference_slices(arr: &Array<bool, Ix3>, axis: usize, zyx_spacing: &[f32], margin: f32) -> Vec<usize> {
+    let n_slices = arr.len_of(Axis(axis));
+    let contoured: Vec<usize> = (0..n_slices)
+        .filter(|i| arr.index_axis(Axis(axis), *i).iter().any(|v| *v))
+        .collect();
+    if contoured.is_empty() {
+        return contoured
+    }
+    let margin_slices = (margin / zyx_spacing[axis]).floor() as usize;
+    let first = contoured[0].saturating_sub(margin_slices);
+    let last = (contoured[contoured.len() - 1] + margin_slices).min(n_slices - 1);
+    (first..last + 1).collect()
+}
+
+/// Lookup table of which of `n_slices` slices are listed in `slices`; out-of-range entries are ignored.
+fn selected_slices(n_slices: usize, slices: &[usize]) -> Vec<bool> {
+    let mut selected = vec![false; n_slices];
+    for i in slices.iter().filter(|i| **i < n_slices) {
+        selected[*i] = true;
+    }
+    selected
+}
+
+/// Copy of `arr` where every slice along `axis` not listed in `slices` is cleared.
+pub fn restrict_to_slices(arr: &Array<bool, Ix3>, slices: &[usize], axis: usize) -> Array<bool, Ix3> {
+    let selected = selected_slices(arr.len_of(Axis(axis)), slices);
+    let mut restricted = arr.clone();
+    for (i, mut slice) in restricted.axis_iter_mut(Axis(axis)).enumerate() {
+        if !selected[i] {
+            slice.fill(false);
+        }
+    }
+    restricted
+}
+
+/// ROI mask of the given shape that is true on the slices along `axis` listed in `slices`,
+/// for use with the metrics' `with_roi`.
+pub fn slice_mask(shape: [usize; 3], slices: &[usize], axis: usize) -> Array<bool, Ix3> {
+    let selected = selected_slices(shape[axis], slices);
+    Array::from_shape_fn(shape, |(z, y, x)| selected[[z, y, x][axis]])
+}
+
+/// Neighbour offsets for 6-, 18- or 26-connectivity.
+pub fn neighbour_offsets(connectivity: u8) -> Vec<[isize; 3]> {
+    let max_nonzero = match connectivity {
+        6 => 1,
+        18 => 2,
+        26 => 3,
+        _ => panic!("connectivity must be 6, 18 or 26, got {}", connectivity),
+    };
+    let mut offsets = Vec::new();
+    for dz in -1..=1_isize {
+        for dy in -1..=1_isize {
+            for dx in -1..=1_isize {
+                let nonzero = [dz, dy, dx].iter().filter(|d| **d != 0).count();
+                if (nonzero > 0) & (nonzero <= max_nonzero) {
+                    offsets.push([dz, dy, dx]);
+                }
+            }
+        }
+    }
+    offsets
+}
+
+/// Labels connected components of `arr` with 1..=n (0 is background). Returns the label map and n.
+pub fn label_components(arr: &Array<bool, Ix3>, connectivity: u8) -> (Array<u32, Ix3>, u32) {
+    let offsets = neighbour_offsets(connectivity);
+    let shape = arr.shape();
+    let mut labels = Array3::<u32>::zeros(arr.raw_dim());
+    let mut n_labels: u32 = 0;
+    let mut queue = VecDeque::<[usize; 3]>::new();
+
+    for ((z, y, x), val) in arr.indexed_iter() {
+        if !*val || labels[[z, y, x]] != 0 {
+            continue;
+        }
+        n_labels += 1;
+        labels[[z, y, x]] = n_labels;
+        queue.push_back([z, y, x]);
+        while let Some(idx) = queue.pop_front() {
+            for offset in &offsets {
+                let mut neighbour = [0_usize; 3];
+                let mut inside = true;
+                for d in 0..3 {
+                    let i = idx[d] as isize + offset[d];
+                    if (i < 0) | (i >= shape[d] as isize) {
+                        inside = false;
+                        break;
                     }
+                    neighbour[d] = i as usize;
+                }
+                if inside && arr[neighbour] && labels[neighbour] == 0 {
+                    labels[neighbour] = n_labels;
+                    queue.push_back(neighbour);
                 }
             }
         }
     }
-    let edge_arr = edge_arr.mapv(|x| (x != 0));
-    edge_arr
+    (labels, n_labels)
+}
+
+/// Inclusive [min, max] index along each axis of the foreground of `arr`, or None if it is empty.
+pub fn bounding_box(arr: &Array<bool, Ix3>) -> Option<[[usize; 2]; 3]> {
+    let mut bbox: Option<[[usize; 2]; 3]> = None;
+    for ((z, y, x), val) in arr.indexed_iter() {
+        if !*val {
+            continue;
+        }
+        let idx = [z, y, x];
+        match bbox.as_mut() {
+            None => bbox = Some([[z, z], [y, y], [x, x]]),
+            Some(b) => {
+                for d in 0..3 {
+                    b[d][0] = b[d][0].min(idx[d]);
+                    b[d][1] = b[d][1].max(idx[d]);
+                }
+            }
+        }
+    }
+    bbox
+}
+
+/// Offsets of all voxels within `radius` mm of the origin, honouring anisotropic spacing.
+pub fn ellipsoid_offsets(zyx_spacing: &[f32], radius: f32) -> Vec<[isize; 3]> {
+    let reach: Vec<isize> = zyx_spacing.iter().map(|s| (radius / s).floor() as isize).collect();
+    let mut offsets = Vec::new();
+    for dz in -reach[0]..=reach[0] {
+        for dy in -reach[1]..=reach[1] {
+            for dx in -reach[2]..=reach[2] {
+                let dist = ((dz as f32 * zyx_spacing[0]).powi(2)
+                    + (dy as f32 * zyx_spacing[1]).powi(2)
+                    + (dx as f32 * zyx_spacing[2]).powi(2)).sqrt();
+                if dist <= radius {
+                    offsets.push([dz, dy, dx]);
+                }
+            }
+        }
+    }
+    offsets
+}
+
+/// Voxels within `width` mm of the surface of `arr` (as given by `generate_edge`), on both sides.
+pub fn surface_band(arr: &Array<bool, Ix3>, zyx_spacing: &[f32], width: f32) -> Array<bool, Ix3> {
+    let offsets = ellipsoid_offsets(zyx_spacing, width);
+    let shape = arr.shape();
+    let mut band = Array3::<bool>::from_elem(arr.raw_dim(), false);
+    let Some([zs, ys, xs]) = joint_crop(&[arr], 0) else { return band };
+    let (z0, y0, x0) = (zs.start, ys.start, xs.start);
+    // All edge voxels lie inside the bounding box of the foreground
+    let edge = generate_edge(&arr.slice(s![zs, ys, xs]).to_owned(), false);
+    for ((z, y, x), val) in edge.indexed_iter() {
+        if !*val {
+            continue;
+        }
+        let (z, y, x) = (z + z0, y + y0, x + x0);
+        for offset in &offsets {
+            let nz = z as isize + offset[0];
+            let ny = y as isize + offset[1];
+            let nx = x as isize + offset[2];
+            if (nz >= 0) & (ny >= 0) & (nx >= 0)
+                & (nz < shape[0] as isize) & (ny < shape[1] as isize) & (nx < shape[2] as isize) {
+                band[[nz as usize, ny as usize, nx as usize]] = true;
+            }
+        }
+    }
+    band
+}
+
+/// Squared distance transform of one line (Felzenszwalb & Huttenlocher), with samples
+/// `spacing` mm apart. `f` holds 0 at feature points and infinity elsewhere.
+fn squared_distance_1d(f: &[f64], spacing: f64) -> Vec<f64> {
+    let n = f.len();
+    let mut out = vec![f64::INFINITY; n];
+    let mut sites = Vec::<usize>::with_capacity(n);
+    let mut bounds = Vec::<f64>::with_capacity(n);
+    for q in 0..n {
+        if f[q].is_infinite() {
+            continue;
+        }
+        let xq = q as f64 * spacing;
+        while let Some(&v) = sites.last() {
+            let xv = v as f64 * spacing;
+            let intersection = ((f[q] + xq * xq) - (f[v] + xv * xv)) / (2.0 * (xq - xv));
+            if intersection <= *bounds.last().unwrap() {
+                sites.pop();
+                bounds.pop();
+            } else {
+                break;
+            }
+        }
+        if sites.is_empty() {
+            bounds.push(f64::NEG_INFINITY);
+        } else {
+            let v = *sites.last().unwrap();
+            let xv = v as f64 * spacing;
+            bounds.push(((f[q] + xq * xq) - (f[v] + xv * xv)) / (2.0 * (xq - xv)));
+        }
+        sites.push(q);
+    }
+    if sites.is_empty() {
+        return out;
+    }
+    let mut k = 0;
+    for (q, o) in out.iter_mut().enumerate() {
+        let xq = q as f64 * spacing;
+        while (k + 1 < sites.len()) && (bounds[k + 1] < xq) {
+            k += 1;
+        }
+        let xv = sites[k] as f64 * spacing;
+        *o = (xq - xv).powi(2) + f[sites[k]];
+    }
+    out
+}
+
+/// Exact Euclidean distance (mm) from every voxel to the nearest true voxel of `arr`.
+/// All distances are infinite if `arr` is empty.
+pub fn distance_transform(arr: &Array<bool, Ix3>, zyx_spacing: &[f32]) -> Array<f32, Ix3> {
+    let mut squared = arr.mapv(|v| if v { 0.0_f64 } else { f64::INFINITY });
+    for (axis, spacing) in zyx_spacing.iter().enumerate() {
+        for mut lane in squared.lanes_mut(Axis(axis)) {
+            let f: Vec<f64> = lane.to_vec();
+            for (dst, src) in lane.iter_mut().zip(squared_distance_1d(&f, *spacing as f64)) {
+                *dst = src;
+            }
+        }
+    }
+    squared.mapv(|d| d.sqrt() as f32)
+}
+
+/// Index into a flattened 3x3x3 neighbourhood.
+fn cube_index(offset: [isize; 3]) -> usize {
+    ((offset[0] + 1) * 9 + (offset[1] + 1) * 3 + (offset[2] + 1)) as usize
+}
+
+/// 3x3x3 neighbourhood of `idx`; voxels outside the volume count as background.
+fn neighbourhood(arr: &Array<bool, Ix3>, idx: [usize; 3]) -> [bool; 27] {
+    let shape = arr.shape();
+    let mut cube = [false; 27];
+    for offset in neighbour_offsets(26) {
+        let z = idx[0] as isize + offset[0];
+        let y = idx[1] as isize + offset[1];
+        let x = idx[2] as isize + offset[2];
+        if (z >= 0) & (y >= 0) & (x >= 0)
+            & (z < shape[0] as isize) & (y < shape[1] as isize) & (x < shape[2] as isize) {
+            cube[cube_index(offset)] = arr[[z as usize, y as usize, x as usize]];
+        }
+    }
+    cube[13] = arr[idx];
+    cube
+}
+
+/// Number of components of `members` (cube indices) under the given connectivity,
+/// counting only components that contain at least one of `seeds`.
+fn cube_components(members: &[usize], connectivity: u8, seeds: &[usize]) -> usize {
+    let offsets = neighbour_offsets(connectivity);
+    let mut label = [0_usize; 27];
+    let mut n_components = 0;
+    for start in seeds {
+        if !members.contains(start) || label[*start] != 0 {
+            continue;
+        }
+        n_components += 1;
+        label[*start] = n_components;
+        let mut stack = vec![*start];
+        while let Some(i) = stack.pop() {
+            let p = [(i / 9) as isize - 1, ((i / 3) % 3) as isize - 1, (i % 3) as isize - 1];
+            for offset in &offsets {
+                let q = [p[0] + offset[0], p[1] + offset[1], p[2] + offset[2]];
+                if q.iter().any(|c| c.abs() > 1) {
+                    continue;
+                }
+                let j = cube_index(q);
+                if members.contains(&j) && label[j] == 0 {
+                    label[j] = n_components;
+                    stack.push(j);
+                }
+            }
+        }
+    }
+    n_components
+}
+
+/// A foreground voxel is simple (its removal preserves topology under 26/6 connectivity)
+/// when its 26-neighbourhood holds exactly one 26-connected foreground component and its
+/// 18-neighbourhood exactly one 6-connected background component touching it.
+fn is_simple_point(cube: &[bool; 27]) -> bool {
+    let mut foreground = Vec::with_capacity(26);
+    let mut background = Vec::with_capacity(18);
+    let mut face_neighbours = Vec::with_capacity(6);
+    for offset in neighbour_offsets(26) {
+        let i = cube_index(offset);
+        let nonzero = offset.iter().filter(|d| **d != 0).count();
+        if cube[i] {
+            foreground.push(i);
+        } else if nonzero <= 2 {
+            background.push(i);
+        }
+        if nonzero == 1 {
+            face_neighbours.push(i);
+        }
+    }
+    (cube_components(&foreground, 26, &foreground) == 1)
+        && (cube_components(&background, 6, &face_neighbours) == 1)
+}
+
+/// Topology-preserving thinning of `arr` to a one-voxel-wide centerline. Simple points are
+/// peeled off in six directional sub-iterations until none can be removed; end points
+/// (exactly one foreground neighbour) are kept so that branches do not shrink.
+pub fn skeletonize(arr: &Array<bool, Ix3>) -> Array<bool, Ix3> {
+    // Thin only the bounding box of the foreground; outside voxels count as background
+    // in both cases, so the result is unchanged
+    let mut full = Array3::<bool>::from_elem(arr.raw_dim(), false);
+    let Some([zs, ys, xs]) = joint_crop(&[arr], 0) else { return full };
+    let crop = s![zs, ys, xs];
+    let mut skeleton = arr.slice(crop).to_owned();
+    let is_end_point = |cube: &[bool; 27]| cube.iter().filter(|v| **v).count() == 2;
+    loop {
+        let mut changed = false;
+        for direction in neighbour_offsets(6) {
+            let mut candidates = Vec::new();
+            for ((z, y, x), val) in skeleton.indexed_iter() {
+                if !*val {
+                    continue;
+                }
+                let cube = neighbourhood(&skeleton, [z, y, x]);
+                if !cube[cube_index(direction)] && !is_end_point(&cube) && is_simple_point(&cube) {
+                    candidates.push([z, y, x]);
+                }
+            }
+            // Re-check sequentially, earlier deletions may have changed the neighbourhood
+            for idx in candidates {
+                let cube = neighbourhood(&skeleton, idx);
+                if !is_end_point(&cube) && is_simple_point(&cube) {
+                    skeleton[idx] = false;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    full.slice_mut(crop).assign(&skeleton);
+    full
+}
+
+/// Length (mm) of a one-voxel-wide centerline: sum of distances between 26-adjacent voxels.
+/// A diagonal step is skipped when the two voxels are also joined through a skeleton voxel
+/// between them, so a staircase or corner is not counted along both paths.
+pub fn centerline_length(skeleton: &Array<bool, Ix3>, zyx_spacing: &[f32]) -> f32 {
+    let shape = skeleton.shape();
+    let is_set = |idx: [usize; 3], offset: [isize; 3]| {
+        let nz = idx[0] as isize + offset[0];
+        let ny = idx[1] as isize + offset[1];
+        let nx = idx[2] as isize + offset[2];
+        (nz >= 0) & (ny >= 0) & (nx >= 0)
+            & (nz < shape[0] as isize) & (ny < shape[1] as isize) & (nx < shape[2] as isize)
+            && skeleton[[nz as usize, ny as usize, nx as usize]]
+    };
+    let mut length = 0.0_f64;
+    for ((z, y, x), val) in skeleton.indexed_iter() {
+        if !*val {
+            continue;
+        }
+        for offset in neighbour_offsets(26) {
+            // Visit each pair once
+            if cube_index(offset) <= 13 || !is_set([z, y, x], offset) {
+                continue;
+            }
+            // Voxels between the pair keep a subset of the offset's nonzero components
+            let bridged = neighbour_offsets(26).iter()
+                .filter(|sub| *sub != &offset && (0..3).all(|d| (sub[d] == 0) | (sub[d] == offset[d])))
+                .any(|sub| is_set([z, y, x], *sub));
+            if !bridged {
+                length += ((offset[0] as f64 * zyx_spacing[0] as f64).powi(2)
+                    + (offset[1] as f64 * zyx_spacing[1] as f64).powi(2)
+                    + (offset[2] as f64 * zyx_spacing[2] as f64).powi(2)).sqrt();
+            }
+        }
+    }
+    length as f32
 }
 
 #[cfg(test)]
@@ -84,8 +455,8 @@ mod test_utils {
             }
         }
 
-        let src = src.mapv(|x| (x != 0));
-        let dst = dst.mapv(|x| (x != 0));
+        let src = src.mapv(|x| x != 0);
+        let dst = dst.mapv(|x| x != 0);
         let edge = generate_edge(&src, true);
         assert!(edge == dst)
     }
@@ -108,11 +479,199 @@ mod test_utils {
             }
         }
 
-        let src = src.mapv(|x| (x != 0));
-        let dst = dst.mapv(|x| (x != 0));
+        let src = src.mapv(|x| x != 0);
+        let dst = dst.mapv(|x| x != 0);
         let edge = generate_edge(&src, false);
 
         assert!(edge == dst)
     }
-}
+    #[test]
+    fn test_generate_edge_cropped() {
+        let mut src = Array3::<bool>::from_elem([6, 8, 9], false);
+        src.slice_mut(s![2..5, 3..6, 4..7]).fill(true);
+        src.slice_mut(s![0..2, 0..2, 7..9]).fill(true);
+        // Reference: foreground voxels whose clipped 3x3x3 (or in-plane 3x3) box is not full
+        for use_2d in [false, true] {
+            let expected = Array3::from_shape_fn(src.raw_dim(), |(z, y, x)| {
+                let z_range = if use_2d { z..z + 1 } else { z.saturating_sub(1)..(z + 2).min(6) };
+                let n_box = z_range.len() * 3 * 3;
+                let window = src.slice(s![z_range, y.saturating_sub(1)..(y + 2).min(8), x.saturating_sub(1)..(x + 2).min(9)]);
+                src[[z, y, x]] & ((window.iter().filter(|v| **v).count() < n_box) | (window.len() < n_box))
+            });
+            assert_eq!(generate_edge(&src, use_2d), expected);
+        }
+        assert_eq!(joint_crop(&[&src], 1), Some([0..6, 0..7, 3..9]));
+        assert!(!generate_edge(&Array3::<bool>::from_elem([2, 2, 2], false), false).iter().any(|v| *v));
+    }
+
+    #[test]
+    fn test_reference_slices() {
+        let mut src = Array3::<bool>::from_elem([10, 3, 3], false);
+        src[[4, 1, 1]] = true;
+        src[[6, 1, 1]] = true;
+
+        assert_eq!(reference_slices(&src, 0, &[2.0, 1.0, 1.0], 0.0), vec![4, 5, 6]);
+        assert_eq!(reference_slices(&src, 0, &[2.0, 1.0, 1.0], 5.0), vec![2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(reference_slices(&src, 0, &[2.0, 1.0, 1.0], 100.0), (0..10).collect::<Vec<usize>>());
+        assert_eq!(reference_slices(&src, 1, &[2.0, 1.0, 1.0], 0.0), vec![1]);
+        assert!(reference_slices(&Array3::<bool>::from_elem([2, 2, 2], false), 0, &[1.0, 1.0, 1.0], 0.0).is_empty());
+    }
+    #[test]
+    fn test_restrict_to_slices() {
+        let src = Array3::<bool>::from_elem([4, 3, 3], true);
+        let restricted = restrict_to_slices(&src, &[1, 2], 0);
+
+        assert_eq!(restricted.iter().filter(|v| **v).count(), 18);
+        assert!(!restricted[[0, 1, 1]] & restricted[[1, 1, 1]] & restricted[[2, 1, 1]] & !restricted[[3, 1, 1]]);
+
+        let roi = slice_mask([4, 3, 3], &[0, 2, 7], 2);
+        assert_eq!(roi.iter().filter(|v| **v).count(), 24);
+        assert!(roi[[3, 1, 0]] & !roi[[3, 1, 1]] & roi[[3, 1, 2]]);
+    }
+    #[test]
+    fn test_label_components() {
+        let mut src = Array3::<bool>::from_elem([3, 4, 4], false);
+        src[[0, 0, 0]] = true;
+        src[[1, 1, 1]] = true;
+        src[[2, 3, 3]] = true;
+        src[[2, 3, 2]] = true;
 
+        let (_, n) = label_components(&src, 6);
+        assert_eq!(n, 3);
+        let (_, n) = label_components(&src, 18);
+        assert_eq!(n, 3);
+        let (labels, n) = label_components(&src, 26);
+        assert_eq!(n, 2);
+        assert_eq!(labels[[0, 0, 0]], labels[[1, 1, 1]]);
+        assert_eq!(labels[[2, 3, 3]], labels[[2, 3, 2]]);
+        assert_eq!(labels[[0, 1, 0]], 0);
+    }
+    #[test]
+    fn test_bounding_box() {
+        let mut src = Array3::<bool>::from_elem([3, 4, 5], false);
+        assert!(bounding_box(&src).is_none());
+        src[[1, 3, 0]] = true;
+        src[[2, 1, 4]] = true;
+        assert_eq!(bounding_box(&src), Some([[1, 2], [1, 3], [0, 4]]));
+    }
+    #[test]
+    fn test_ellipsoid_offsets() {
+        assert_eq!(ellipsoid_offsets(&[1.0, 1.0, 1.0], 0.5).len(), 1);
+        assert_eq!(ellipsoid_offsets(&[1.0, 1.0, 1.0], 1.0).len(), 7);
+        assert_eq!(ellipsoid_offsets(&[3.0, 1.0, 1.0], 2.0).len(), 13);
+    }
+    #[test]
+    fn test_surface_band() {
+        let mut src = Array3::<bool>::from_elem([1, 1, 9], false);
+        for x in 2..7 {
+            src[[0, 0, x]] = true;
+        }
+        let band = surface_band(&src, &[1.0, 1.0, 2.0], 2.0);
+        let expected = Array3::<u8>::from(vec![[[0, 1, 1, 1, 1, 1, 1, 1, 0]]]).mapv(|x| x != 0);
+        assert!(band == expected);
+    }
+    #[test]
+    fn test_distance_transform() {
+        let mut src = Array3::<bool>::from_elem([3, 4, 5], false);
+        src[[0, 0, 0]] = true;
+        src[[2, 3, 4]] = true;
+        let spacing = [2.0, 1.5, 0.5];
+        let dt = distance_transform(&src, &spacing);
+
+        for ((z, y, x), d) in dt.indexed_iter() {
+            let to_first = ((z as f32 * 2.0).powi(2) + (y as f32 * 1.5).powi(2) + (x as f32 * 0.5).powi(2)).sqrt();
+            let to_second = (((2 - z) as f32 * 2.0).powi(2) + ((3 - y) as f32 * 1.5).powi(2) + ((4 - x) as f32 * 0.5).powi(2)).sqrt();
+            assert!((d - to_first.min(to_second)).abs() < 1e-5);
+        }
+        let dt = distance_transform(&Array3::<bool>::from_elem([2, 2, 2], false), &spacing);
+        assert!(dt.iter().all(|d| d.is_infinite()));
+    }
+    #[test]
+    fn test_skeletonize() {
+        let mut src = Array3::<bool>::from_elem([5, 5, 12], false);
+        for z in 1..4 {
+            for y in 1..4 {
+                for x in 1..11 {
+                    src[[z, y, x]] = true;
+                }
+            }
+        }
+        let skeleton = skeletonize(&src);
+        let n_voxels = skeleton.iter().filter(|v| **v).count();
+
+        assert!(skeleton.iter().zip(src.iter()).all(|(s, a)| !*s | *a));
+        assert_eq!(label_components(&skeleton, 26).1, 1);
+        assert!((6..=10).contains(&n_voxels));
+        assert!(skeleton.indexed_iter().filter(|(_, v)| **v).all(|((z, y, _), _)| (z == 2) & (y == 2)));
+    }
+    #[test]
+    fn test_skeletonize_keeps_loop() {
+        let mut src = Array3::<bool>::from_elem([3, 7, 7], false);
+        for y in 1..6 {
+            for x in 1..6 {
+                src[[1, y, x]] = (y == 1) | (y == 5) | (x == 1) | (x == 5);
+            }
+        }
+        let skeleton = skeletonize(&src);
+        // Only the corners are simple, the ring itself must survive
+        for corner in [[1, 1, 1], [1, 1, 5], [1, 5, 1], [1, 5, 5]] {
+            src[corner] = false;
+        }
+        assert!(skeleton == src);
+    }
+    #[test]
+    fn test_centerline_length() {
+        let mut src = Array3::<bool>::from_elem([1, 3, 5], false);
+        for x in 0..4 {
+            src[[0, 1, x]] = true;
+        }
+        src[[0, 2, 4]] = true;
+        assert!((centerline_length(&src, &[1.0, 1.0, 2.0]) - (6.0 + 5.0_f32.sqrt())).abs() < 1e-6);
+
+        // Staircase: the diagonal shortcuts across each corner are not counted
+        let mut stairs = Array3::<bool>::from_elem([1, 4, 4], false);
+        for (y, x) in [(0, 0), (0, 1), (1, 1), (1, 2), (2, 2), (2, 3), (3, 3)] {
+            stairs[[0, y, x]] = true;
+        }
+        assert!((centerline_length(&stairs, &[1.0, 1.0, 1.0]) - 6.0).abs() < 1e-6);
+        // L-shape in 3D with a face-diagonal corner
+        let mut corner = Array3::<bool>::from_elem([3, 3, 3], false);
+        for idx in [[0, 0, 0], [1, 0, 0], [2, 0, 0], [2, 1, 1], [2, 2, 2]] {
+            corner[idx] = true;
+        }
+        assert!((centerline_length(&corner, &[1.0, 1.0, 1.0]) - (2.0 + 2.0 * 2.0_f32.sqrt())).abs() < 1e-6);
+    }
+    /// Joint box of the separate per-array bounding boxes, one full pass per array.
+    fn joint_crop_per_array(arrs: &[&Array<bool, Ix3>], margin: usize) -> Option<[Range<usize>; 3]> {
+        let mut joint: Option<[[usize; 2]; 3]> = None;
+        for bbox in arrs.iter().filter_map(|arr| bounding_box(arr)) {
+            joint = Some(match joint {
+                None => bbox,
+                Some(j) => [0, 1, 2].map(|d| [j[d][0].min(bbox[d][0]), j[d][1].max(bbox[d][1])]),
+            });
+        }
+        let shape = arrs[0].shape();
+        joint.map(|j| [0, 1, 2].map(|d| j[d][0].saturating_sub(margin)..(j[d][1] + margin + 1).min(shape[d])))
+    }
+    #[test]
+    fn test_joint_crop() {
+        let mut a = Array3::<bool>::from_elem([6, 7, 8], false);
+        let mut b = Array3::<bool>::from_elem([6, 7, 8], false);
+        a[[2, 5, 1]] = true;
+        a[[3, 1, 4]] = true;
+        b[[4, 3, 6]] = true;
+        assert_eq!(joint_crop(&[&a, &b], 0), Some([2..5, 1..6, 1..7]));
+        assert_eq!(joint_crop(&[&a, &b], 2), Some([0..6, 0..7, 0..8]));
+        assert_eq!(joint_crop(&[&b], 1), Some([3..6, 2..5, 5..8]));
+        let empty = Array3::<bool>::from_elem([6, 7, 8], false);
+        assert_eq!(joint_crop(&[&empty, &b], 0), joint_crop(&[&b], 0));
+        assert_eq!(joint_crop(&[&empty], 0), None);
+
+        let mut state: u32 = 7;
+        for val in a.iter_mut().chain(b.iter_mut()) {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            *val = (state >> 16).is_multiple_of(37);
+        }
+        assert_eq!(joint_crop(&[&a, &b], 1), joint_crop_per_array(&[&a, &b], 1));
+    }
+}
@@ -0,0 +1,78 @@
+use ndarray::{Array, Ix3};
+use crate::overlap::ConfusionMatrix;
+
+/// Volume of a single voxel in mm³.
+pub fn voxel_volume(zyx_spacing: &[f32]) -> f32 {
+    zyx_spacing.iter().product()
+}
+
+/// Volume of the foreground of `arr` in mm³; divide by 1000 for ml.
+pub fn volume(arr: &Array<bool, Ix3>, zyx_spacing: &[f32]) -> f32 {
+    (arr.iter().filter(|v| **v).count() as f64 * voxel_volume(zyx_spacing) as f64) as f32
+}
+
+/// Volumes (in ml) of a reference and another mask and their differences.
+pub struct VolumeDifference {
+    cm: ConfusionMatrix,
+    zyx_spacing: Vec<f32>,
+}
+
+impl VolumeDifference {
+    pub fn new(ref_arr: &Array<bool, Ix3>, other_arr: &Array<bool, Ix3>, zyx_spacing: Vec<f32>) -> VolumeDifference {
+        VolumeDifference {
+            cm: ConfusionMatrix::new(ref_arr, other_arr),
+            zyx_spacing,
+        }
+    }
+    pub fn execute(&mut self) {
+        self.cm.execute();
+    }
+    pub fn ref_volume(&self) -> f32 {
+        (self.cm.ref_count() as f64 * voxel_volume(&self.zyx_spacing) as f64 / 1000.0) as f32
+    }
+    pub fn other_volume(&self) -> f32 {
+        (self.cm.other_count() as f64 * voxel_volume(&self.zyx_spacing) as f64 / 1000.0) as f32
+    }
+    /// |other - ref| in ml.
+    pub fn abs_volume_difference(&self) -> f32 {
+        (self.other_volume() - self.ref_volume()).abs()
+    }
+    /// (other - ref) / ref; positive when the other mask is larger.
+    pub fn relative_volume_difference(&self) -> f32 {
+        (self.other_volume() - self.ref_volume()) / self.ref_volume()
+    }
+    /// 1 - |other - ref| / (other + ref).
+    pub fn volume_similarity(&self) -> f32 {
+        1.0 - self.abs_volume_difference() / (self.other_volume() + self.ref_volume())
+    }
+}
+
+#[cfg(test)]
+mod test_volume {
+    use ndarray::Array3;
+    use crate::volume::{volume, voxel_volume, VolumeDifference};
+
+    #[test]
+    fn test_volume_difference() {
+        let src = Array3::<bool>::from_elem([10, 10, 10], true);
+        let mut dst = Array3::<bool>::from_elem([10, 10, 10], false);
+        for z in 0..5 {
+            for y in 0..10 {
+                for x in 0..10 {
+                    dst[[z, y, x]] = true;
+                }
+            }
+        }
+        assert_eq!(voxel_volume(&[2.0, 1.0, 0.5]), 1.0);
+        assert_eq!(volume(&dst, &[2.0, 1.0, 3.0]), 3000.0);
+
+        let mut vd = VolumeDifference::new(&src, &dst, vec![2.0, 1.0, 0.5]);
+        vd.execute();
+
+        assert_eq!(vd.ref_volume(), 1.0);
+        assert_eq!(vd.other_volume(), 0.5);
+        assert_eq!(vd.abs_volume_difference(), 0.5);
+        assert_eq!(vd.relative_volume_difference(), -0.5);
+        assert_eq!(vd.volume_similarity(), 1.0 - 0.5 / 1.5);
+    }
+}